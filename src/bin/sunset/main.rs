@@ -1,15 +1,34 @@
-use clap::{ArgAction, Parser, Subcommand};
+mod output;
+mod registry;
+
+use clap::{ArgAction, Args, Parser, Subcommand, ValueEnum};
+use serde::Serialize;
 use std::env;
+use std::fmt::Display;
 use std::fs;
+use std::io::{self, Write};
 use std::path;
 use std::process;
+use std::sync::OnceLock;
 
-use winreg::RegKey;
-use winreg::enums::HKEY_CURRENT_USER;
-use winreg::enums::KEY_ALL_ACCESS;
+use sunset::SunsetError;
+use sunset::bundle::{self, ConflictPolicy, ImportOutcome};
+use sunset::config;
+use sunset::descriptor;
+use sunset::doctor::{Finding, Severity};
+use sunset::manifest::{self, SyncAction, SyncReason, SyncStep};
+use sunset::pathlist;
+use sunset::pattern;
+use sunset::project;
+use sunset::prune::{PruneItem, PruneReason};
+use sunset::quoting;
+use sunset::shim::{self, ShimConfigEnvAction, ShimConfigEnvActionItem};
+use sunset::shimdir::DirShims;
+use sunset::shimmer::{self, DirOverrides, ExeState, NewShim, ShimStatus, Sunset, UpgradedShim};
+use toml_edit::DocumentMut;
 
-use sunset::shim;
-use sunset::shimmer;
+use output::{BulkItem, Format, Output};
+use registry::EnvValue;
 
 /// Create shims to executables with default arguments and environment in Windows.
 #[derive(Parser)]
@@ -18,15 +37,65 @@ use sunset::shimmer;
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+
+    /// Output format. json prints structured records for scripts.
+    #[arg(long, global = true, value_enum, default_value_t = Format::Text)]
+    format: Format,
+
+    /// Shorthand for --format json.
+    #[arg(long, global = true, action=ArgAction::SetTrue)]
+    json: bool,
+
+    /// Directory of the shims.
+    /// Defaults to shims_path in the sunset.toml config file, or %LOCALAPPDATA%\sunset\shims
+    #[arg(
+        long,
+        global = true,
+        env = "SUNSET_SHIMS_PATH",
+        value_name = "SHIMS PATH"
+    )]
+    shims_path: Option<path::PathBuf>,
+
+    /// Directory of the shim and shimw executables copied for new shims.
+    /// Defaults to templates_path in the sunset.toml config file, or the directory of sunset
+    #[arg(
+        long,
+        global = true,
+        env = "SUNSET_TEMPLATES_PATH",
+        value_name = "TEMPLATES PATH"
+    )]
+    templates_path: Option<path::PathBuf>,
 }
 
+/// Directories given with --shims-path and --templates-path, set once in `main`.
+static DIR_OVERRIDES: OnceLock<DirOverrides> = OnceLock::new();
+
 #[derive(Subcommand)]
 enum Commands {
     /// Initializes sunset by creating the base path for shims and adding it to the PATH environment variable.
     Init {
-        /// Path to create the shims in
-        #[arg(long, env = "SUNSET_SHIMS_PATH", name = "SHIMS PATH")]
-        shims_path: Option<String>,
+        /// Add the shims path at the beginning of the PATH, so shims take precedence over other executables.
+        #[arg(long, action=ArgAction::SetTrue, conflicts_with = "append")]
+        prepend: Option<bool>,
+
+        /// Add the shims path at the end of the PATH (default).
+        #[arg(long, action=ArgAction::SetTrue)]
+        append: Option<bool>,
+
+        /// Restore the PATH environment variable from the latest backup made by sunset, and exit.
+        #[arg(long, action=ArgAction::SetTrue, conflicts_with_all = ["prepend", "append"])]
+        restore_path: Option<bool>,
+    },
+
+    /// Reverts init by removing the shims path from the PATH environment variable and deleting SUNSET_SHIMS_PATH.
+    Uninit {
+        /// Also delete the shims, and the shims directory if nothing else is left in it.
+        #[arg(long, action=ArgAction::SetTrue)]
+        remove_shims: Option<bool>,
+
+        /// Remove the shims without asking for confirmation.
+        #[arg(long, short, action=ArgAction::SetTrue)]
+        yes: Option<bool>,
     },
 
     /// Create shim for executable
@@ -35,19 +104,8 @@ enum Commands {
         #[arg(long, name = "SHIM NAME")]
         shim_name: Option<String>,
 
-        /// Specifies if the shim target is a GUI windows application.
-        /// For a GUI application, sunset uses the shimw application that doesn't require a terminal.
-        #[arg(long, action=ArgAction::SetTrue)]
-        win: Option<bool>,
-
-        /// Specifies if the shim target should not create a terminal window.
-        /// Use with --win if the application launches a terminal window along its GUI.
-        #[arg(long, action=ArgAction::SetTrue)]
-        hidden: Option<bool>,
-
-        /// Does not wait for the termination of the target application.
-        #[arg(long, action=ArgAction::SetFalse)]
-        no_wait: Option<bool>,
+        #[command(flatten)]
+        options: ShimOptions,
 
         /// Path of the target application
         #[arg(value_parser)]
@@ -58,6 +116,36 @@ enum Commands {
         args: Vec<String>,
     },
 
+    /// Create a shim for every executable in a directory, recorded as a group
+    ShimDir {
+        /// Directory with the executables
+        #[arg(value_parser, name = "DIR")]
+        dir: path::PathBuf,
+
+        /// Only shim executables whose name matches this glob (e.g. "java*"). Can be repeated.
+        #[arg(long, value_name = "GLOB")]
+        include: Vec<String>,
+
+        /// Don't shim executables whose name matches this glob. Can be repeated.
+        #[arg(long, value_name = "GLOB")]
+        exclude: Vec<String>,
+
+        /// Prefix for the shim names.
+        #[arg(long, default_value = "")]
+        prefix: String,
+
+        /// Suffix for the shim names.
+        #[arg(long, default_value = "")]
+        suffix: String,
+
+        #[command(flatten)]
+        options: ShimOptions,
+
+        /// Additional arguments for every target application
+        #[arg(value_parser, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+
     /// Display the path to the shim descriptor
     Path {
         /// Name of the shim to display the path for (it may not exists).
@@ -71,66 +159,597 @@ enum Commands {
         /// Name of the shim descriptor
         #[arg(value_parser, name = "SHIM NAME")]
         shim_name: String,
+
+        /// Show the descriptor as the shim sees it, with aliases followed and extends merged.
+        #[arg(long, action=ArgAction::SetTrue)]
+        resolved: Option<bool>,
+    },
+
+    /// Edit a shim descriptor in $VISUAL or $EDITOR, validating it before saving
+    Edit {
+        /// Name of the shim descriptor
+        #[arg(value_parser, name = "SHIM NAME")]
+        shim_name: String,
+    },
+
+    /// Set a key of a shim descriptor, keeping the rest of the file as is
+    Set {
+        /// Name of the shim descriptor
+        #[arg(value_parser, name = "SHIM NAME")]
+        shim_name: String,
+
+        /// Key to set
+        #[arg(value_parser = descriptor::SCALAR_KEYS)]
+        key: String,
+
+        /// Value for the key
+        #[arg(value_parser, allow_hyphen_values = true)]
+        value: String,
+    },
+
+    /// Remove a key from a shim descriptor, so its default is used
+    Unset {
+        /// Name of the shim descriptor
+        #[arg(value_parser, name = "SHIM NAME")]
+        shim_name: String,
+
+        /// Key to remove
+        #[arg(value_parser = descriptor::SCALAR_KEYS)]
+        key: String,
+    },
+
+    /// Modify the default arguments of a shim descriptor
+    Args {
+        /// Name of the shim descriptor
+        #[arg(value_parser, name = "SHIM NAME")]
+        shim_name: String,
+
+        #[command(subcommand)]
+        command: ArgsCommands,
+    },
+
+    /// Modify or list the environment actions of a shim descriptor
+    Env {
+        /// Name of the shim descriptor
+        #[arg(value_parser, name = "SHIM NAME")]
+        shim_name: String,
+
+        #[command(subcommand)]
+        command: EnvCommands,
     },
 
     /// Remove a shim
     Remove {
         /// Name of the shim descriptor to be removed
-        #[arg(value_parser, name = "SHIM NAME")]
+        #[arg(value_parser, name = "SHIM NAME", conflicts_with = "group")]
         shim_name: Option<String>,
+
+        /// Remove every shim in this group.
+        #[arg(long)]
+        group: Option<String>,
+
+        /// Also remove the aliases of the shim, which would no longer work.
+        #[arg(long, action=ArgAction::SetTrue)]
+        with_aliases: Option<bool>,
+    },
+
+    /// Rename a shim, keeping its descriptor as is
+    Rename {
+        /// Name of the shim descriptor
+        #[arg(value_parser, name = "SHIM NAME")]
+        shim_name: String,
+
+        /// New name for the shim
+        #[arg(value_parser, name = "NEW NAME")]
+        new_name: String,
+    },
+
+    /// Create a shim with another name that uses the descriptor of an existing one
+    Alias {
+        /// Name of the new shim
+        #[arg(value_parser, name = "ALIAS")]
+        alias: String,
+
+        /// Name of the shim descriptor to use
+        #[arg(value_parser, name = "SHIM NAME")]
+        shim_name: String,
     },
 
     /// Upgrade the shim executable to be used for a shim
     Upgrade {
         /// Name of the shim descriptor to be upgraded
-        #[arg(value_parser, name = "SHIM NAME")]
+        #[arg(value_parser, name = "SHIM NAME", conflicts_with = "group")]
+        shim_name: Option<String>,
+
+        /// Upgrade every shim in this group.
+        #[arg(long)]
+        group: Option<String>,
+    },
+
+    /// Remove the executable of a shim, so it stops running, keeping its descriptor
+    Disable {
+        /// Name of the shim descriptor to be disabled
+        #[arg(
+            value_parser,
+            name = "SHIM NAME",
+            conflicts_with = "group",
+            required_unless_present = "group"
+        )]
         shim_name: Option<String>,
+
+        /// Disable every shim in this group.
+        #[arg(long)]
+        group: Option<String>,
+    },
+
+    /// Restore the executable of a disabled shim
+    Enable {
+        /// Name of the shim descriptor to be enabled
+        #[arg(
+            value_parser,
+            name = "SHIM NAME",
+            conflicts_with = "group",
+            required_unless_present = "group"
+        )]
+        shim_name: Option<String>,
+
+        /// Enable every shim in this group.
+        #[arg(long)]
+        group: Option<String>,
+    },
+
+    /// Select the active variant of a shim, or list its variants
+    Use {
+        /// Name of the shim descriptor
+        #[arg(value_parser, name = "SHIM NAME")]
+        shim_name: String,
+
+        /// Variant to make active. If not given, list the variants.
+        #[arg(value_parser, name = "VARIANT")]
+        variant: Option<String>,
     },
 
     /// List the available shims
-    List {},
+    List {
+        /// Show the target, mode, flags, argument count and executable state of every shim.
+        #[arg(long, short, action=ArgAction::SetTrue)]
+        long: Option<bool>,
+
+        /// Sort the shims by this field.
+        #[arg(long, value_enum, default_value_t = ListSort::Name)]
+        sort: ListSort,
+
+        /// Only list shims that cannot run: invalid descriptor, missing target or missing executable.
+        #[arg(long, action=ArgAction::SetTrue)]
+        broken: Option<bool>,
+
+        /// Only list shims for GUI applications (created with --win).
+        #[arg(long, action=ArgAction::SetTrue)]
+        gui: Option<bool>,
+
+        /// Only list shims whose name matches this glob (e.g. "java*").
+        #[arg(long, name = "GLOB")]
+        name: Option<String>,
+
+        /// Only list shims in this group.
+        #[arg(long)]
+        group: Option<String>,
+
+        /// Only list shims with this tag.
+        #[arg(long)]
+        tag: Option<String>,
+    },
 
     /// Upgrade all the shim executables for all available shims
     UpgradeAll {},
+
+    /// Show what runs for a command, resolving it through the PATH and any shims to the real executable
+    Which {
+        /// Command to resolve, as typed in a terminal
+        #[arg(value_parser, name = "COMMAND")]
+        command: String,
+    },
+
+    /// Run the target of a shim descriptor like its shim executable would, without needing it
+    Run {
+        /// Name of the shim descriptor
+        #[arg(value_parser, name = "SHIM NAME")]
+        shim_name: String,
+
+        /// Arguments passed to the target after the ones in the descriptor
+        #[arg(value_parser, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+
+    /// Remove orphaned executables, orphaned descriptors and shims whose target doesn't exist
+    Prune {
+        /// Only show what would be removed.
+        #[arg(long, action=ArgAction::SetTrue)]
+        dry_run: Option<bool>,
+
+        /// Remove without asking for confirmation.
+        #[arg(long, short, action=ArgAction::SetTrue)]
+        yes: Option<bool>,
+    },
+
+    /// Create, update and remove shims to match a manifest file
+    Sync {
+        /// Manifest listing the shims to have
+        #[arg(value_parser, name = "MANIFEST")]
+        manifest: path::PathBuf,
+
+        /// Only show the plan, and exit with an error if the shims don't match the manifest.
+        #[arg(long, action=ArgAction::SetTrue)]
+        check: Option<bool>,
+
+        /// Take over existing shims listed in the manifest that were made by hand or by another manifest.
+        #[arg(long, action=ArgAction::SetTrue)]
+        adopt: Option<bool>,
+    },
+
+    /// Print a bundle with the descriptors of the shims, to import them in another machine
+    Export {
+        /// Only export these shims.
+        #[arg(long, num_args = 1.., value_name = "SHIM NAME")]
+        names: Vec<String>,
+
+        /// Rewrite absolute paths under directories like %LOCALAPPDATA% or %USERPROFILE% to reference them.
+        #[arg(long, action=ArgAction::SetTrue)]
+        rewrite_paths: Option<bool>,
+    },
+
+    /// Create the shims of a bundle made by export
+    Import {
+        /// Bundle file
+        #[arg(value_parser, name = "BUNDLE")]
+        bundle: path::PathBuf,
+
+        /// What to do with shims that already exist: keep them, replace them, or import with another name.
+        #[arg(long, value_enum, default_value_t = OnConflict::Skip)]
+        on_conflict: OnConflict,
+    },
+
+    /// Use the .sunset.toml overrides of a project directory
+    Trust {
+        /// Project directory. Defaults to the current directory.
+        #[arg(value_parser, name = "DIR")]
+        dir: Option<path::PathBuf>,
+    },
+
+    /// Stop using the .sunset.toml overrides of a project directory
+    Untrust {
+        /// Project directory. Defaults to the current directory.
+        #[arg(value_parser, name = "DIR")]
+        dir: Option<path::PathBuf>,
+    },
+
+    /// Diagnose the sunset installation and the health of every shim
+    Doctor {
+        /// Apply the available fixes for the problems found.
+        #[arg(long, action=ArgAction::SetTrue)]
+        fix: Option<bool>,
+    },
+}
+
+/// Options of the shims created by `shim` and `shim-dir`.
+#[derive(Args)]
+struct ShimOptions {
+    /// Specifies if the shim target is a GUI windows application.
+    /// For a GUI application, sunset uses the shimw application that doesn't require a terminal.
+    #[arg(long, action=ArgAction::SetTrue)]
+    win: Option<bool>,
+
+    /// Uses the terminal shim application, overriding a `win` default in sunset.toml.
+    #[arg(long, action=ArgAction::SetTrue, conflicts_with = "win")]
+    no_win: Option<bool>,
+
+    /// Specifies if the shim target should not create a terminal window.
+    /// Use with --win if the application launches a terminal window along its GUI.
+    #[arg(long, action=ArgAction::SetTrue)]
+    hidden: Option<bool>,
+
+    /// Lets the target create a terminal window, overriding a `hidden` default in sunset.toml.
+    #[arg(long, action=ArgAction::SetTrue, conflicts_with = "hidden")]
+    no_hidden: Option<bool>,
+
+    /// Waits for the termination of the target application, overriding a `wait` default in
+    /// sunset.toml.
+    #[arg(long, action=ArgAction::SetTrue)]
+    wait: Option<bool>,
+
+    /// Does not wait for the termination of the target application.
+    #[arg(long, action=ArgAction::SetTrue, conflicts_with = "wait")]
+    no_wait: Option<bool>,
+
+    /// Set an environment variable for the target, as VAR=value. Can be repeated.
+    #[arg(long, value_name = "VAR=VALUE", value_parser = parse_env_assignment)]
+    env: Vec<(String, String)>,
+
+    /// Append a value to an environment variable, as VAR=value. Can be repeated.
+    #[arg(long, value_name = "VAR=VALUE", value_parser = parse_env_assignment)]
+    env_append: Vec<(String, String)>,
+
+    /// Prepend a value to an environment variable, as VAR=value. Can be repeated.
+    #[arg(long, value_name = "VAR=VALUE", value_parser = parse_env_assignment)]
+    env_prepend: Vec<(String, String)>,
+
+    /// Remove an environment variable for the target. Can be repeated.
+    /// Variables are removed first, then set, prepended and appended.
+    #[arg(long, value_name = "VAR")]
+    env_unset: Vec<String>,
+
+    /// Separator between the current value and the ones from --env-append and --env-prepend (e.g. ";").
+    #[arg(long, default_value = "")]
+    separator: String,
+
+    /// Expand environment variables in the target path when the shim runs.
+    #[arg(long, action=ArgAction::SetTrue)]
+    env_expand_path: Option<bool>,
+
+    /// Don't expand environment variables in the target path, overriding sunset.toml.
+    #[arg(long, action=ArgAction::SetTrue, conflicts_with = "env_expand_path")]
+    no_env_expand_path: Option<bool>,
+
+    /// Expand environment variables in the arguments when the shim runs.
+    #[arg(long, action=ArgAction::SetTrue)]
+    env_expand_args: Option<bool>,
+
+    /// Don't expand environment variables in the arguments, overriding sunset.toml.
+    #[arg(long, action=ArgAction::SetTrue, conflicts_with = "env_expand_args")]
+    no_env_expand_args: Option<bool>,
+
+    /// Working directory for the target application.
+    #[arg(long)]
+    cwd: Option<String>,
+
+    /// Group of the shims, to manage them together.
+    /// shim-dir defaults to the directory name, or its parent's for a bin directory.
+    #[arg(long)]
+    group: Option<String>,
+
+    /// Tag the shims with a label. Can be repeated.
+    #[arg(long = "tag", value_name = "TAG")]
+    tags: Vec<String>,
+}
+
+#[derive(Subcommand)]
+enum ArgsCommands {
+    /// Append arguments
+    Add {
+        #[arg(value_parser, required = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+
+    /// Remove every occurrence of the arguments
+    Remove {
+        #[arg(value_parser, required = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+
+    /// Remove all the arguments
+    Clear {},
+}
+
+#[derive(Subcommand)]
+enum EnvCommands {
+    /// Add an action on an environment variable
+    Add {
+        /// Name of the environment variable
+        #[arg(value_parser)]
+        var: String,
+
+        /// Value to set, append or prepend. Not used with --action clear.
+        #[arg(value_parser, allow_hyphen_values = true)]
+        value: Option<String>,
+
+        /// Action on the variable: set, clear, append or prepend.
+        #[arg(long, default_value = "set")]
+        action: String,
+
+        /// Separator between the current value and the appended or prepended one.
+        #[arg(long, default_value = "")]
+        separator: String,
+    },
+
+    /// Remove every action on an environment variable
+    Remove {
+        /// Name of the environment variable
+        #[arg(value_parser)]
+        var: String,
+    },
+
+    /// List the actions on environment variables
+    List {},
 }
 
 fn main() {
     let cli = Cli::parse();
 
+    let out = Output {
+        format: if cli.json { Format::Json } else { cli.format },
+    };
+
+    let _ = DIR_OVERRIDES.set(DirOverrides {
+        shims_dir: cli.shims_path.clone(),
+        templates_dir: cli.templates_path.clone(),
+    });
+
     match &cli.command {
-        Commands::Init { shims_path } => shim_init(shims_path),
+        Commands::Init {
+            prepend,
+            append: _,
+            restore_path,
+        } => {
+            if restore_path.unwrap_or(false) {
+                shim_restore_path()
+            } else {
+                let position = if prepend.unwrap_or(false) {
+                    pathlist::Position::Prepend
+                } else {
+                    pathlist::Position::Append
+                };
+                shim_init(&cli.shims_path, position)
+            }
+        }
+        Commands::Uninit { remove_shims, yes } => {
+            shim_uninit(remove_shims.unwrap_or(false), yes.unwrap_or(false))
+        }
         Commands::Shim {
             shim_name,
-            win,
-            hidden,
-            no_wait,
+            options,
             path: target_path,
             args,
-        } => shimmer::shim(target_path, args, shim_name, win, hidden, no_wait),
-        Commands::Path { shim_name } => shimmer::shim_path(shim_name),
-        Commands::Info { shim_name } => shimmer::shim_info(shim_name),
-        Commands::Remove { shim_name } => shimmer::shim_remove(shim_name),
-        Commands::Upgrade { shim_name } => shim_upgrade(shim_name),
-        Commands::List {} => shim_list(),
-        Commands::UpgradeAll {} => shim_upgrade_all(),
+        } => shim(&out, &options.new_shim(target_path, shim_name, args)),
+        Commands::ShimDir {
+            dir,
+            include,
+            exclude,
+            prefix,
+            suffix,
+            options,
+            args,
+        } => {
+            let spec = DirShims {
+                dir: dir.clone(),
+                include: include.clone(),
+                exclude: exclude.clone(),
+                prefix: prefix.clone(),
+                suffix: suffix.clone(),
+                group: options.group.clone(),
+                shared: options.new_shim("", &None, args),
+            };
+            shim_dir(&out, &spec)
+        }
+        Commands::Path { shim_name } => shim_path(&out, shim_name),
+        Commands::Info {
+            shim_name,
+            resolved,
+        } => shim_info(&out, shim_name, resolved.unwrap_or(false)),
+        Commands::Edit { shim_name } => shim_edit(&out, shim_name),
+        Commands::Set {
+            shim_name,
+            key,
+            value,
+        } => shim_set(&out, shim_name, key, value),
+        Commands::Unset { shim_name, key } => shim_unset(&out, shim_name, key),
+        Commands::Args { shim_name, command } => shim_args(&out, shim_name, command),
+        Commands::Env { shim_name, command } => shim_env(&out, shim_name, command),
+        Commands::Remove {
+            shim_name,
+            group,
+            with_aliases,
+        } => match group {
+            Some(group) => shim_remove_group(&out, group, with_aliases.unwrap_or(false)),
+            None => shim_remove(&out, shim_name, with_aliases.unwrap_or(false)),
+        },
+        Commands::Rename {
+            shim_name,
+            new_name,
+        } => shim_rename(&out, shim_name, new_name),
+        Commands::Alias { alias, shim_name } => shim_alias(&out, alias, shim_name),
+        Commands::Upgrade { shim_name, group } => match group {
+            Some(group) => shim_upgrade_group(&out, group),
+            None => shim_upgrade(&out, shim_name),
+        },
+        Commands::Disable { shim_name, group } => shim_toggle(&out, shim_name, group, true),
+        Commands::Enable { shim_name, group } => shim_toggle(&out, shim_name, group, false),
+        Commands::Use { shim_name, variant } => shim_use(&out, shim_name, variant),
+        Commands::List {
+            long,
+            sort,
+            broken,
+            gui,
+            name,
+            group,
+            tag,
+        } => shim_list(
+            &out,
+            long.unwrap_or(false),
+            *sort,
+            &ListFilter {
+                broken: broken.unwrap_or(false),
+                gui: gui.unwrap_or(false),
+                name: name.clone(),
+                group: group.clone(),
+                tag: tag.clone(),
+            },
+        ),
+        Commands::UpgradeAll {} => shim_upgrade_all(&out),
+        Commands::Which { command } => shim_which(&out, command),
+        Commands::Run { shim_name, args } => shim_run(&out, shim_name, args),
+        Commands::Prune { dry_run, yes } => {
+            shim_prune(&out, dry_run.unwrap_or(false), yes.unwrap_or(false))
+        }
+        Commands::Sync {
+            manifest,
+            check,
+            adopt,
+        } => shim_sync(
+            &out,
+            manifest,
+            check.unwrap_or(false),
+            adopt.unwrap_or(false),
+        ),
+        Commands::Export {
+            names,
+            rewrite_paths,
+        } => shim_export(&out, names, rewrite_paths.unwrap_or(false)),
+        Commands::Import {
+            bundle,
+            on_conflict,
+        } => shim_import(&out, bundle, *on_conflict),
+        Commands::Trust { dir } => shim_trust(&out, dir, true),
+        Commands::Untrust { dir } => shim_trust(&out, dir, false),
+        Commands::Doctor { fix } => shim_doctor(&out, fix.unwrap_or(false)),
     };
 }
 
-fn shim_init(shims_path: &Option<String>) {
-    let localappdata_path = match env::var("LOCALAPPDATA") {
-        Ok(var_value) => path::Path::new(&var_value).join("sunset\\shims"),
-        Err(e) => {
-            println!("Failed to get value of {}: {}", "LOCALAPPDATA", e);
-            process::exit(-1);
-        }
-    };
+fn fail(err: impl Display) -> ! {
+    println!("{}", err);
+    process::exit(-1);
+}
+
+/// Asks a yes/no question in the terminal. Anything but "y" or "yes" is a no.
+fn confirm(question: &str) -> bool {
+    print!("{} [y/N] ", question);
+    let _ = io::stdout().flush();
+
+    let mut answer = String::new();
+    if io::stdin().read_line(&mut answer).is_err() {
+        return false;
+    }
+
+    matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
+
+fn load_sunset(out: &Output) -> Sunset {
+    let dirs = DIR_OVERRIDES.get().cloned().unwrap_or_default();
+
+    config::load()
+        .and_then(|config| Sunset::from_config(config, dirs))
+        .unwrap_or_else(|err| out.fail(err))
+}
 
+/// Flags given in the command line, `--x` or `--no-x`, override the defaults, otherwise they
+/// are left to them.
+fn flag(on: &Option<bool>, off: &Option<bool>) -> Option<bool> {
+    match (on, off) {
+        (Some(true), _) => Some(true),
+        (_, Some(true)) => Some(false),
+        _ => None,
+    }
+}
+
+fn shim_init(shims_path: &Option<path::PathBuf>, position: pathlist::Position) {
     let selected_shims_path = match shims_path {
-        Some(value) => path::Path::new(value),
-        None => localappdata_path.as_path(),
+        Some(value) => value.clone(),
+        None => match config::load().unwrap_or_else(|err| fail(err)).shims_path {
+            Some(value) => value,
+            None => shimmer::get_default_shims_dir().unwrap_or_else(|err| fail(err)),
+        },
     };
 
-    let selected_shims_path_str = selected_shims_path.to_str().unwrap();
+    let selected_shims_path_str = selected_shims_path.to_string_lossy();
 
     println!("Selected shims path: {:?}", selected_shims_path);
 
@@ -141,7 +760,7 @@ fn shim_init(shims_path: &Option<String>) {
 
     // ENSURE SHIMS_PATH exists
 
-    match fs::create_dir_all(selected_shims_path) {
+    match fs::create_dir_all(&selected_shims_path) {
         Ok(_value) => true,
         Err(e) => {
             println!("Error creating directories: {}", e);
@@ -149,17 +768,17 @@ fn shim_init(shims_path: &Option<String>) {
         }
     };
 
-    let hkcu = RegKey::predef(HKEY_CURRENT_USER);
-    let env_key = hkcu
-        .open_subkey_with_flags("Environment", KEY_ALL_ACCESS)
-        .unwrap();
-
     println!(
         "Setting SUNSET_SHIMS_PATH environment variable to {}",
         selected_shims_path_str
     );
 
-    match env_key.set_value("SUNSET_SHIMS_PATH", &selected_shims_path_str) {
+    let shims_path_value = EnvValue {
+        value: selected_shims_path_str.to_string(),
+        expandable: false,
+    };
+
+    match registry::set_var("SUNSET_SHIMS_PATH", &shims_path_value) {
         Ok(_) => {}
         Err(err) => {
             println!("Error setting env var: {}", err);
@@ -167,21 +786,24 @@ fn shim_init(shims_path: &Option<String>) {
         }
     };
 
-    // Get the current value of the PATH variable
-    let current_path: String = env_key.get_value("PATH").unwrap();
+    let current_path = read_path();
 
-    let paths: Vec<&str> = current_path.split(";").collect();
-
-    let is_present = paths.iter().any(|&part| part == selected_shims_path_str);
+    let in_place = match position {
+        pathlist::Position::Prepend => {
+            pathlist::is_first(&current_path.value, &selected_shims_path_str)
+        }
+        pathlist::Position::Append => {
+            pathlist::contains(&current_path.value, &selected_shims_path_str)
+        }
+    };
 
-    if !is_present {
-        // Append your directory to the current PATH
-        let new_path = format!("{};{}", current_path, selected_shims_path_str);
+    if !in_place {
+        // An entry further down the PATH is moved to the front when prepending.
+        let new_path = pathlist::place(&current_path.value, &selected_shims_path_str, position);
 
         println!("Setting PATH environment variable to: {}", new_path);
 
-        // Update the PATH value in the registry
-        env_key.set_value("PATH", &new_path).unwrap();
+        write_path(&current_path, &new_path, current_path.expandable);
     } else {
         println!("{} already on PATH", selected_shims_path_str);
     }
@@ -189,59 +811,1400 @@ fn shim_init(shims_path: &Option<String>) {
     println!("Restart processes or machine to apply environment variables changes.");
 }
 
-fn shim_upgrade(shim_name: &Option<String>) {
-    let shim_name = match shim_name {
-        None => {
-            println!("shim name not specified");
-            process::exit(-1);
-        }
-        Some(value) => value,
+fn shim_restore_path() {
+    let backup_path = match registry::latest_path_backup() {
+        Ok(Some(path)) => path,
+        Ok(None) => fail("No PATH backups found"),
+        Err(err) => fail(format!("Cannot find PATH backups: {}", err)),
+    };
+
+    println!("Restoring PATH from {:?}", backup_path);
+
+    let backup = match registry::read_path_backup(&backup_path) {
+        Ok(backup) => backup,
+        Err(err) => fail(format!("Cannot read backup {:?}: {}", backup_path, err)),
     };
 
-    let sunset_dir = shimmer::get_sunset_dir();
-    let shims_dir = shimmer::get_shims_dir();
+    println!("Setting PATH environment variable to: {}", backup.value);
 
-    let shim_path = shimmer::get_shimfile(&shims_dir, &shim_name);
-    let shimmed_exe_path = shimmer::get_shimmed_exe(&shims_dir, &shim_name);
+    // Not backed up, so restoring again restores the same backup instead of undoing this one.
+    set_path(&backup.value, backup.expandable);
 
-    if !shim_path.exists() {
-        println!("Shim {:?} doesn't exists", shim_name);
-        process::exit(-1);
+    println!("Restart processes or machine to apply environment variables changes.");
+}
+
+fn read_path() -> EnvValue {
+    match registry::get_var("PATH") {
+        Ok(Some(value)) => value,
+        Ok(None) => EnvValue {
+            value: String::new(),
+            expandable: true,
+        },
+        Err(err) => fail(format!("Error reading PATH env var: {}", err)),
+    }
+}
+
+/// Writes `new_value` to PATH, backing up `current_path` first.
+fn write_path(current_path: &EnvValue, new_value: &str, expandable: bool) {
+    match registry::write_path_backup(current_path) {
+        Ok(backup_path) => println!("Previous PATH saved to {:?}", backup_path),
+        Err(err) => fail(format!("Cannot back up PATH: {}", err)),
     }
 
-    let config = shim::read_config(shim_path.as_path()).expect("Error reading file");
-    let shim_exe = shimmer::get_shim_exe(&sunset_dir, &config.win);
+    set_path(new_value, expandable);
+}
 
-    println!("Upgrading {:?} with {:?}", shimmed_exe_path, shim_exe);
+/// Writes `new_value` to PATH, without a backup.
+fn set_path(new_value: &str, expandable: bool) {
+    let new_path = EnvValue {
+        value: String::from(new_value),
+        expandable,
+    };
 
-    shimmer::shim_create(&shim_exe, &shimmed_exe_path);
+    match registry::set_var("PATH", &new_path) {
+        Ok(_) => {}
+        Err(err) => fail(format!("Error setting env var: {}", err)),
+    }
 }
 
-fn shim_list() {
-    let shim_dir = shimmer::get_shims_dir();
-    let shim_list = shimmer::shim_list(&shim_dir);
+/// Files of the shims in `shims_dir`: descriptors, their executables and the `templates`
+/// directory. Other files are left alone.
+fn shim_files(shims_dir: &path::Path) -> Vec<path::PathBuf> {
+    let names = shimmer::shim_list(shims_dir).unwrap_or_else(|err| fail(err));
+
+    let mut files: Vec<path::PathBuf> = names
+        .iter()
+        .flat_map(|name| {
+            [
+                shimmer::get_shimfile(shims_dir, name),
+                shimmer::get_shimmed_exe(shims_dir, name),
+            ]
+        })
+        .filter(|file| file.exists())
+        .collect();
 
-    for shim in shim_list {
-        println!("{}", &shim);
+    let templates = shims_dir.join(shim::TEMPLATES_DIR);
+    if templates.is_dir() {
+        files.push(templates);
     }
+
+    files.sort();
+    files
 }
 
-fn shim_upgrade_all() {
-    let sunset_dir = shimmer::get_sunset_dir();
-    let shim_dir = shimmer::get_shims_dir();
-    let shim_list = shimmer::shim_list(&shim_dir);
+fn shim_uninit(remove_shims: bool, yes: bool) {
+    let shims_path = match registry::get_var("SUNSET_SHIMS_PATH") {
+        Ok(Some(value)) => value.value,
+        _ => match env::var("SUNSET_SHIMS_PATH") {
+            Ok(value) => value,
+            Err(_) => fail("SUNSET_SHIMS_PATH is not set, sunset doesn't seem to be initialized"),
+        },
+    };
+
+    let shims_dir = path::Path::new(&shims_path);
+
+    // Asked before changing anything, so declining leaves sunset initialized.
+    let shim_files = if remove_shims && shims_dir.is_dir() {
+        let files = shim_files(shims_dir);
+
+        println!("The following files will be removed:");
+
+        for file in &files {
+            println!("  {}", file.display());
+        }
+
+        if !yes && !confirm(&format!("Remove the shims in {:?}?", shims_dir)) {
+            println!("Aborted");
+            return;
+        }
+
+        files
+    } else {
+        Vec::new()
+    };
+
+    println!("Uninitializing sunset, shims in path {:?}", shims_path);
+
+    let current_path = read_path();
 
-    for it in shim_list {
-        let shim_path = shimmer::get_shimfile(&shim_dir, &it);
-        let shimmed_exe_path = shimmer::get_shimmed_exe(&shim_dir, &it);
+    if pathlist::contains(&current_path.value, &shims_path) {
+        let new_path = pathlist::remove(&current_path.value, &shims_path);
 
-        println!("Upgrading shim {:?}", shim_path);
+        println!("Setting PATH environment variable to: {}", new_path);
+
+        write_path(&current_path, &new_path, current_path.expandable);
+    } else {
+        println!("{} not on PATH", shims_path);
+    }
+
+    println!("Removing SUNSET_SHIMS_PATH environment variable");
+
+    match registry::delete_var("SUNSET_SHIMS_PATH") {
+        Ok(_) => {}
+        Err(err) => fail(format!("Error removing env var: {}", err)),
+    }
 
-        let config = shim::read_config(shim_path.as_path()).expect("Error reading file");
-        let shim_exe = shimmer::get_shim_exe(&sunset_dir, &config.win);
+    if remove_shims {
+        if shims_dir.is_dir() {
+            for file in &shim_files {
+                let removed = if file.is_dir() {
+                    fs::remove_dir_all(file)
+                } else {
+                    fs::remove_file(file)
+                };
 
-        println!("Upgrading {:?} with {:?}", shimmed_exe_path, shim_exe);
+                if let Err(err) = removed {
+                    fail(format!("Cannot remove {:?}: {}", file, err));
+                }
+            }
 
-        shimmer::shim_create(&shim_exe, &shimmed_exe_path);
+            // Only an empty directory is removed, it may hold files that aren't shims.
+            match fs::remove_dir(shims_dir) {
+                Ok(_) => println!("Removed {:?}", shims_dir),
+                Err(_) => println!("Kept {:?}, it has other files", shims_dir),
+            }
+        } else {
+            println!("{:?} doesn't exists", shims_dir);
+        }
+    }
+
+    println!("Restart processes or machine to apply environment variables changes.");
+}
+
+/// Parses a VAR=value command line argument.
+fn parse_env_assignment(value: &str) -> Result<(String, String), String> {
+    match value.split_once('=') {
+        Some((var, value)) if !var.is_empty() => Ok((String::from(var), String::from(value))),
+        _ => Err(format!("expected VAR=value, got {:?}", value)),
+    }
+}
+
+impl ShimOptions {
+    fn new_shim(&self, target: &str, name: &Option<String>, args: &[String]) -> NewShim {
+        NewShim {
+            target: String::from(target),
+            name: name.clone(),
+            args: args.to_vec(),
+            win: flag(&self.win, &self.no_win),
+            hidden: flag(&self.hidden, &self.no_hidden),
+            wait: flag(&self.wait, &self.no_wait),
+            env_expand_path: flag(&self.env_expand_path, &self.no_env_expand_path),
+            env_expand_args: flag(&self.env_expand_args, &self.no_env_expand_args),
+            env: self.env_actions(),
+            cwd: self.cwd.clone(),
+            group: self.group.clone(),
+            tags: self.tags.clone(),
+        }
+    }
+
+    /// Environment actions from the flags: unset first, then set, prepend and append.
+    fn env_actions(&self) -> Vec<ShimConfigEnvActionItem> {
+        let item = |action, var: &str, value: &str, separator: &str| ShimConfigEnvActionItem {
+            var: String::from(var),
+            action,
+            value: String::from(value),
+            separator: String::from(separator),
+        };
+
+        let mut items = Vec::new();
+
+        for var in &self.env_unset {
+            items.push(item(ShimConfigEnvAction::Clear, var, "", ""));
+        }
+
+        for (var, value) in &self.env {
+            items.push(item(ShimConfigEnvAction::Set, var, value, ""));
+        }
+
+        for (var, value) in &self.env_prepend {
+            items.push(item(
+                ShimConfigEnvAction::Prepend,
+                var,
+                value,
+                &self.separator,
+            ));
+        }
+
+        for (var, value) in &self.env_append {
+            items.push(item(
+                ShimConfigEnvAction::Append,
+                var,
+                value,
+                &self.separator,
+            ));
+        }
+
+        items
+    }
+}
+
+fn shim(out: &Output, new_shim: &NewShim) {
+    let sunset = load_sunset(out);
+
+    let created = sunset.create(new_shim).unwrap_or_else(|err| out.fail(err));
+
+    if out.is_json() {
+        out.record(&created);
+        return;
+    }
+
+    println!(
+        "Shimming {:?}/ {:?} => {:?} using {:?}",
+        created.exe, created.shimfile, created.target, created.template
+    );
+
+    println!("Done");
+}
+
+fn shim_dir(out: &Output, spec: &DirShims) {
+    let sunset = load_sunset(out);
+    let new_shims = sunset.dir_shims(spec).unwrap_or_else(|err| out.fail(err));
+
+    if new_shims.is_empty() {
+        out.fail(format!("No executables to shim in {}", spec.dir.display()));
+    }
+
+    let items: Vec<_> = new_shims
+        .iter()
+        .map(|new_shim| {
+            BulkItem::from_result(
+                new_shim.name.as_deref().unwrap_or_default(),
+                sunset.create(new_shim),
+            )
+        })
+        .collect();
+
+    if out.is_json() {
+        out.record(&items);
+    } else {
+        for item in &items {
+            match (&item.result, &item.error) {
+                (Some(created), _) => println!("Shimmed {} => {:?}", created.name, created.target),
+                (None, error) => println!(
+                    "Cannot shim {:?}: {}",
+                    item.name,
+                    error.as_deref().unwrap_or_default()
+                ),
+            }
+        }
+
+        let created = items.iter().filter(|item| item.success).count();
+        match new_shims.first().and_then(|it| it.group.as_deref()) {
+            Some(group) => println!("Created {} shims in group {}", created, group),
+            None => println!("Created {} shims", created),
+        }
+    }
+
+    if items.iter().any(|item| !item.success) {
+        process::exit(-1);
+    }
+}
+
+#[derive(Serialize)]
+struct PathRecord {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    path: path::PathBuf,
+    exists: bool,
+}
+
+fn shim_path(out: &Output, name: &Option<String>) {
+    let sunset = load_sunset(out);
+
+    let path = match name {
+        None => sunset.shims_dir.clone(),
+        Some(name) => sunset.shimfile(name),
+    };
+
+    if out.is_json() {
+        out.record(&PathRecord {
+            name: name.clone(),
+            exists: path.exists(),
+            path,
+        });
+    } else {
+        println!("{}", path.display());
+    }
+}
+
+fn shim_info(out: &Output, name: &str, resolved: bool) {
+    let sunset = load_sunset(out);
+    let info = if resolved {
+        sunset.info_resolved(name)
+    } else {
+        sunset.info(name)
+    }
+    .unwrap_or_else(|err| out.fail(err));
+
+    if out.is_json() {
+        out.record(&info);
+    } else {
+        println!("{}", info.content);
+    }
+}
+
+/// Editor command from $VISUAL or $EDITOR, split in program and arguments.
+fn get_editor() -> Vec<String> {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or(String::from(if cfg!(windows) { "notepad" } else { "vi" }));
+
+    editor.split_whitespace().map(String::from).collect()
+}
+
+fn shim_edit(out: &Output, name: &str) {
+    let sunset = load_sunset(out);
+    let info = sunset.info(name).unwrap_or_else(|err| out.fail(err));
+
+    let tmp_path = env::temp_dir().join(format!("sunset-{}-{}.toml", name, process::id()));
+
+    if let Err(err) = fs::write(&tmp_path, &info.content) {
+        out.fail(format!("Cannot write file {:?}: {}", tmp_path, err));
+    }
+
+    let editor = get_editor();
+
+    let result = loop {
+        let status = process::Command::new(&editor[0])
+            .args(&editor[1..])
+            .arg(&tmp_path)
+            .status();
+
+        match status {
+            Ok(status) if status.success() => {}
+            Ok(status) => break Err(format!("Editor exited with {}", status)),
+            Err(err) => break Err(format!("Cannot run editor {:?}: {}", editor[0], err)),
+        }
+
+        let content = match fs::read_to_string(&tmp_path) {
+            Ok(content) => content,
+            Err(err) => break Err(format!("Cannot read file {:?}: {}", tmp_path, err)),
+        };
+
+        if content == info.content {
+            break Ok(None);
+        }
+
+        match sunset.update_descriptor(name, &content) {
+            Ok(config) => break Ok(Some(config)),
+            Err(err) => {
+                println!("{}", err);
+
+                if !confirm("Edit again?") {
+                    break Err(String::from("Aborted, descriptor left unchanged"));
+                }
+            }
+        }
+    };
+
+    let _ = fs::remove_file(&tmp_path);
+
+    match result {
+        Ok(None) => out.text("No changes"),
+        Ok(Some(config)) => {
+            if out.is_json() {
+                out.record(&config);
+            } else {
+                println!("Updated {}", info.shimfile.display());
+            }
+        }
+        Err(err) => out.fail(err),
+    }
+}
+
+/// Applies `edit` to the descriptor of a shim and reports the result.
+fn edit_descriptor<F>(out: &Output, name: &str, edit: F)
+where
+    F: FnOnce(&mut DocumentMut) -> sunset::error::Result<()>,
+{
+    let sunset = load_sunset(out);
+    let config = sunset
+        .edit_descriptor(name, edit)
+        .unwrap_or_else(|err| out.fail(err));
+
+    if out.is_json() {
+        out.record(&config);
+    } else {
+        println!("Updated {}", sunset.shimfile(name).display());
+    }
+}
+
+fn shim_set(out: &Output, name: &str, key: &str, value: &str) {
+    edit_descriptor(out, name, |doc| descriptor::set_key(doc, key, value));
+}
+
+fn shim_unset(out: &Output, name: &str, key: &str) {
+    edit_descriptor(out, name, |doc| descriptor::unset_key(doc, key));
+}
+
+fn shim_args(out: &Output, name: &str, command: &ArgsCommands) {
+    edit_descriptor(out, name, |doc| match command {
+        ArgsCommands::Add { args } => descriptor::add_args(doc, args),
+        ArgsCommands::Remove { args } => descriptor::remove_args(doc, args),
+        ArgsCommands::Clear {} => descriptor::clear_args(doc),
+    });
+}
+
+fn shim_env(out: &Output, name: &str, command: &EnvCommands) {
+    match command {
+        EnvCommands::Add {
+            var,
+            value,
+            action,
+            separator,
+        } => {
+            let action = ShimConfigEnvAction::from_name(action).unwrap_or_else(|| {
+                out.fail(format!(
+                    "Invalid action {:?}, use set, clear, append or prepend",
+                    action
+                ))
+            });
+
+            if action != ShimConfigEnvAction::Clear && value.is_none() {
+                out.fail(format!("A value is required to {} {}", action.name(), var));
+            }
+
+            let item = ShimConfigEnvActionItem {
+                var: var.clone(),
+                action,
+                value: value.clone().unwrap_or_default(),
+                separator: separator.clone(),
+            };
+
+            edit_descriptor(out, name, |doc| descriptor::add_env(doc, &item));
+        }
+        EnvCommands::Remove { var } => {
+            edit_descriptor(out, name, |doc| match descriptor::remove_env(doc, var)? {
+                0 => Err(SunsetError::InvalidArgument(format!(
+                    "No environment action for {}",
+                    var
+                ))),
+                _ => Ok(()),
+            })
+        }
+        EnvCommands::List {} => {
+            let sunset = load_sunset(out);
+            let info = sunset.info(name).unwrap_or_else(|err| out.fail(err));
+
+            if out.is_json() {
+                out.record(&info.config.env);
+                return;
+            }
+
+            for item in &info.config.env {
+                match item.action {
+                    ShimConfigEnvAction::Clear => println!("clear {}", item.var),
+                    _ if item.separator.is_empty() => {
+                        println!("{} {}={}", item.action.name(), item.var, item.value)
+                    }
+                    _ => println!(
+                        "{} {}={} (separator {:?})",
+                        item.action.name(),
+                        item.var,
+                        item.value,
+                        item.separator
+                    ),
+                }
+            }
+        }
+    }
+}
+
+fn shim_remove(out: &Output, name: &Option<String>, with_aliases: bool) {
+    let name = match name {
+        None => out.fail("shim name not specified"),
+        Some(value) => value,
+    };
+
+    let sunset = load_sunset(out);
+
+    out.text(format!(
+        "Removing shim {:?} ({:?}, {:?})",
+        name,
+        sunset.shimmed_exe(name),
+        sunset.shimfile(name)
+    ));
+
+    let removed = sunset
+        .remove(name, with_aliases)
+        .unwrap_or_else(|err| out.fail(err));
+
+    if out.is_json() {
+        out.record(&removed);
+        return;
+    }
+
+    for alias in &removed.aliases {
+        println!("Removed alias {:?}", alias.name);
+    }
+}
+
+fn shim_rename(out: &Output, name: &str, new_name: &str) {
+    let sunset = load_sunset(out);
+    let renamed = sunset
+        .rename(name, new_name)
+        .unwrap_or_else(|err| out.fail(err));
+
+    if out.is_json() {
+        out.record(&renamed);
+        return;
+    }
+
+    println!("Renamed {:?} to {:?}", renamed.name, renamed.new_name);
+
+    for alias in &renamed.aliases {
+        println!("Updated alias {:?}", alias);
+    }
+
+    for other in &renamed.extending {
+        println!("Updated {:?} to extend {:?}", other, renamed.new_name);
+    }
+}
+
+fn shim_alias(out: &Output, alias: &str, name: &str) {
+    let sunset = load_sunset(out);
+    let created = sunset
+        .alias(alias, name)
+        .unwrap_or_else(|err| out.fail(err));
+
+    if out.is_json() {
+        out.record(&created);
+        return;
+    }
+
+    println!(
+        "Aliasing {:?} => {:?} using {:?}",
+        created.exe, name, created.template
+    );
+
+    println!("Done");
+}
+
+/// Names of the shims in `group`, failing if there are none.
+fn group_members(out: &Output, sunset: &Sunset, group: &str) -> Vec<String> {
+    let names = sunset
+        .group_members(group)
+        .unwrap_or_else(|err| out.fail(err));
+
+    if names.is_empty() {
+        out.fail(format!("No shims in group {}", group));
+    }
+
+    names
+}
+
+fn shim_remove_group(out: &Output, group: &str, with_aliases: bool) {
+    let sunset = load_sunset(out);
+
+    let members = group_members(out, &sunset, group);
+    let alias_of = |name: &String| {
+        fs::read_to_string(sunset.shimfile(name))
+            .ok()
+            .and_then(|content| shim::alias_of(&content))
+    };
+
+    // Aliases are in the group of the shim they refer to. Those of removed shims are left to
+    // `remove`, which refuses without `with_aliases` like for a single shim.
+    let names: Vec<&String> = members
+        .iter()
+        .filter(|name| alias_of(name).is_none_or(|target| !members.contains(&target)))
+        .collect();
+
+    let items: Vec<_> = names
+        .iter()
+        .map(|name| BulkItem::from_result(name, sunset.remove(name, with_aliases)))
+        .collect();
+
+    if out.is_json() {
+        out.record(&items);
+    } else {
+        for item in &items {
+            match (&item.result, &item.error) {
+                (Some(removed), _) => {
+                    println!("Removed shim {:?}", item.name);
+                    for alias in &removed.aliases {
+                        println!("Removed alias {:?}", alias.name);
+                    }
+                }
+                (None, error) => println!(
+                    "Cannot remove shim {:?}: {}",
+                    item.name,
+                    error.as_deref().unwrap_or_default()
+                ),
+            }
+        }
+    }
+
+    if items.iter().any(|item| !item.success) {
+        process::exit(-1);
+    }
+}
+
+fn shim_toggle(out: &Output, name: &Option<String>, group: &Option<String>, disable: bool) {
+    let sunset = load_sunset(out);
+
+    let names = match (name, group) {
+        (_, Some(group)) => group_members(out, &sunset, group),
+        (Some(name), None) => vec![name.clone()],
+        (None, None) => out.fail("shim name not specified"),
+    };
+
+    let items: Vec<_> = names
+        .iter()
+        .map(|name| {
+            let result = if disable {
+                sunset.disable(name)
+            } else {
+                sunset.enable(name)
+            };
+            BulkItem::from_result(name, result)
+        })
+        .collect();
+
+    if out.is_json() {
+        out.record(&items);
+    } else {
+        let (done, verb) = if disable {
+            ("Disabled", "disable")
+        } else {
+            ("Enabled", "enable")
+        };
+
+        for item in &items {
+            match &item.error {
+                None => println!("{} shim {:?}", done, item.name),
+                Some(error) => println!("Cannot {} shim {:?}: {}", verb, item.name, error),
+            }
+        }
+    }
+
+    if items.iter().any(|item| !item.success) {
+        process::exit(-1);
+    }
+}
+
+#[derive(Serialize)]
+struct VariantsRecord {
+    name: String,
+    variants: Vec<String>,
+    active: Option<String>,
+    /// Variant the shim selects when run from here.
+    selected: Option<String>,
+}
+
+fn shim_use(out: &Output, name: &str, variant: &Option<String>) {
+    let sunset = load_sunset(out);
+
+    if let Some(variant) = variant {
+        let config = sunset
+            .use_variant(name, variant)
+            .unwrap_or_else(|err| out.fail(err));
+
+        if out.is_json() {
+            out.record(&config);
+        } else {
+            println!("Using {} {} => {}", name, variant, config.path);
+        }
+        return;
+    }
+
+    let info = sunset.info(name).unwrap_or_else(|err| out.fail(err));
+    let selected = shim::read_config_for(&info.shimfile, name)
+        .unwrap_or_else(|err| out.fail(err))
+        .active;
+
+    if out.is_json() {
+        out.record(&VariantsRecord {
+            name: String::from(name),
+            variants: info.config.variants,
+            active: info.config.active,
+            selected,
+        });
+        return;
+    }
+
+    if info.config.variants.is_empty() {
+        println!("Shim {:?} has no variants", name);
+        return;
+    }
+
+    for variant in &info.config.variants {
+        let marker = if Some(variant) == selected.as_ref() {
+            "*"
+        } else {
+            " "
+        };
+        println!("{} {}", marker, variant);
+    }
+
+    if selected != info.config.active {
+        println!(
+            "(overridden by {} or a {} file)",
+            shim::variant_var(name),
+            shim::VERSION_FILE
+        );
+    }
+}
+
+fn shim_upgrade(out: &Output, shim_name: &Option<String>) {
+    let shim_name = match shim_name {
+        None => out.fail("shim name not specified"),
+        Some(value) => value,
+    };
+
+    let sunset = load_sunset(out);
+    let upgraded = sunset
+        .upgrade(shim_name)
+        .unwrap_or_else(|err| out.fail(err));
+
+    if out.is_json() {
+        out.record(&upgraded);
+    } else {
+        println!("Upgraded {:?} with {:?}", upgraded.exe, upgraded.template);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ListSort {
+    Name,
+    Target,
+    State,
+}
+
+struct ListFilter {
+    broken: bool,
+    gui: bool,
+    name: Option<String>,
+    group: Option<String>,
+    tag: Option<String>,
+}
+
+impl ListFilter {
+    fn accepts(&self, status: &ShimStatus) -> bool {
+        let name_matches = match &self.name {
+            Some(glob) => pattern::matches(glob, &status.name),
+            None => true,
+        };
+
+        let config = status.config.as_ref();
+        let is_gui = config.is_some_and(|config| config.win);
+
+        let group_matches =
+            self.group.is_none() || config.is_some_and(|config| config.group == self.group);
+
+        let tag_matches = match &self.tag {
+            Some(tag) => config.is_some_and(|config| config.tags.contains(tag)),
+            None => true,
+        };
+
+        name_matches
+            && group_matches
+            && tag_matches
+            && (!self.broken || status.is_broken())
+            && (!self.gui || is_gui)
+    }
+}
+
+fn shim_list(out: &Output, long: bool, sort: ListSort, filter: &ListFilter) {
+    let sunset = load_sunset(out);
+
+    let mut statuses: Vec<ShimStatus> = sunset
+        .list_status()
+        .unwrap_or_else(|err| out.fail(err))
+        .into_iter()
+        .filter(|status| filter.accepts(status))
+        .collect();
+
+    match sort {
+        ListSort::Name => {}
+        ListSort::Target => statuses.sort_by(|a, b| a.target.cmp(&b.target)),
+        ListSort::State => statuses.sort_by_key(|status| !status.is_broken()),
+    }
+
+    if out.is_json() {
+        out.record(&statuses);
+        return;
+    }
+
+    if !long {
+        for status in statuses {
+            let alias = status
+                .config
+                .as_ref()
+                .and_then(|config| config.target_shim.as_ref());
+
+            match (status.exe_state, alias) {
+                (ExeState::Disabled, _) => println!("{} (disabled)", &status.name),
+                (_, Some(target_shim)) => println!("{} (alias of {})", &status.name, target_shim),
+                _ => println!("{}", &status.name),
+            }
+        }
+        return;
+    }
+
+    let rows: Vec<[String; 6]> = statuses.iter().map(list_row).collect();
+    let header = ["NAME", "MODE", "FLAGS", "ARGS", "EXE", "TARGET"].map(String::from);
+
+    let mut widths = header.clone().map(|it| it.len());
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    for row in std::iter::once(&header).chain(rows.iter()) {
+        let line: Vec<String> = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect();
+        println!("{}", line.join("  ").trim_end());
+    }
+}
+
+fn list_row(status: &ShimStatus) -> [String; 6] {
+    let exe = match status.exe_state {
+        ExeState::Current => "ok",
+        ExeState::Outdated => "outdated",
+        ExeState::Missing => "missing",
+        ExeState::Unknown => "unknown",
+        ExeState::Disabled => "disabled",
+    };
+
+    let config = match &status.config {
+        Some(config) => config,
+        None => {
+            return [
+                status.name.clone(),
+                String::from("-"),
+                String::from("-"),
+                String::from("-"),
+                String::from(exe),
+                String::from("(invalid descriptor)"),
+            ];
+        }
+    };
+
+    let mode = if config.win { "gui" } else { "console" };
+
+    let mut flags = Vec::new();
+    if config.hidden {
+        flags.push("hidden");
+    }
+    if !config.wait {
+        flags.push("nowait");
+    }
+
+    let target = status.target.clone().unwrap_or_default();
+    let target = if status.target_exists {
+        target
+    } else {
+        format!("{} (missing)", target)
+    };
+
+    [
+        status.name.clone(),
+        String::from(mode),
+        if flags.is_empty() {
+            String::from("-")
+        } else {
+            flags.join(",")
+        },
+        config.args.len().to_string(),
+        String::from(exe),
+        target,
+    ]
+}
+
+fn shim_upgrade_all(out: &Output) {
+    let sunset = load_sunset(out);
+    let shim_list = sunset.list().unwrap_or_else(|err| out.fail(err));
+
+    upgrade_many(out, &sunset, &shim_list);
+}
+
+fn shim_upgrade_group(out: &Output, group: &str) {
+    let sunset = load_sunset(out);
+    let names = group_members(out, &sunset, group);
+
+    upgrade_many(out, &sunset, &names);
+}
+
+/// Outcome of one shim in a bulk upgrade: upgraded, or skipped with a reason.
+#[derive(Serialize)]
+struct UpgradeRecord {
+    #[serde(flatten)]
+    upgraded: Option<UpgradedShim>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    skipped: Option<String>,
+}
+
+/// Upgrades the shims in `names`, skipping the disabled ones.
+fn upgrade_many(out: &Output, sunset: &Sunset, names: &[String]) {
+    let items: Vec<BulkItem<UpgradeRecord>> = names
+        .iter()
+        .map(|name| {
+            let record = if sunset.is_disabled(name) {
+                Ok(UpgradeRecord {
+                    upgraded: None,
+                    skipped: Some(String::from("disabled")),
+                })
+            } else {
+                sunset.upgrade(name).map(|upgraded| UpgradeRecord {
+                    upgraded: Some(upgraded),
+                    skipped: None,
+                })
+            };
+
+            BulkItem::from_result(name, record)
+        })
+        .collect();
+
+    if out.is_json() {
+        out.record(&items);
+    } else {
+        for item in &items {
+            match (&item.result, &item.error) {
+                (
+                    Some(UpgradeRecord {
+                        upgraded: Some(upgraded),
+                        ..
+                    }),
+                    _,
+                ) => println!("Upgraded {:?} with {:?}", upgraded.exe, upgraded.template),
+                (Some(record), _) => println!(
+                    "Skipped {} shim {:?}",
+                    record.skipped.as_deref().unwrap_or_default(),
+                    item.name
+                ),
+                (None, error) => println!(
+                    "Cannot upgrade shim {:?}: {}",
+                    item.name,
+                    error.as_deref().unwrap_or_default()
+                ),
+            }
+        }
+    }
+
+    if items.iter().any(|item| !item.success) {
+        process::exit(-1);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OnConflict {
+    Skip,
+    Overwrite,
+    Rename,
+}
+
+fn shim_export(out: &Output, names: &[String], rewrite_paths: bool) {
+    let sunset = load_sunset(out);
+    let bundle = sunset
+        .export(names, rewrite_paths)
+        .unwrap_or_else(|err| out.fail(err));
+
+    if out.is_json() {
+        out.record(&bundle);
+    } else {
+        print!("{}", bundle.to_toml().unwrap_or_else(|err| out.fail(err)));
+    }
+}
+
+fn shim_import(out: &Output, bundle_path: &path::Path, on_conflict: OnConflict) {
+    let sunset = load_sunset(out);
+    let bundle = bundle::read_bundle(bundle_path).unwrap_or_else(|err| out.fail(err));
+
+    let policy = match on_conflict {
+        OnConflict::Skip => ConflictPolicy::Skip,
+        OnConflict::Overwrite => ConflictPolicy::Overwrite,
+        OnConflict::Rename => ConflictPolicy::Rename,
+    };
+
+    // Aliases need the shims they refer to, so they go last, and templates go first.
+    let mut shims = bundle.shims;
+    shims.sort_by_key(|shim| shim::alias_of(&shim.descriptor).is_some());
+
+    let templates = bundle.templates.iter().map(|template| {
+        let name = format!("{}/{}", shim::TEMPLATES_DIR, template.name);
+        BulkItem::from_result(&name, sunset.import_template(template, policy))
+    });
+
+    let items: Vec<_> = templates
+        .chain(
+            shims
+                .iter()
+                .map(|shim| BulkItem::from_result(&shim.name, sunset.import(shim, policy))),
+        )
+        .collect();
+
+    if out.is_json() {
+        out.record(&items);
+    } else {
+        for item in &items {
+            match (&item.result, &item.error) {
+                (Some(imported), _) => match imported.outcome {
+                    ImportOutcome::Created => println!("Created {}", imported.name),
+                    ImportOutcome::Overwritten => println!("Overwritten {}", imported.name),
+                    ImportOutcome::Renamed => {
+                        println!("Created {} as {}", imported.name, imported.shim_name)
+                    }
+                    ImportOutcome::Skipped => {
+                        println!("Skipped {}, it already exists", imported.name)
+                    }
+                },
+                (None, error) => println!(
+                    "Cannot import shim {:?}: {}",
+                    item.name,
+                    error.as_deref().unwrap_or_default()
+                ),
+            }
+        }
+    }
+
+    if items.iter().any(|item| !item.success) {
+        process::exit(-1);
+    }
+}
+
+#[derive(Serialize)]
+struct DoctorRecord {
+    #[serde(flatten)]
+    finding: Finding,
+    /// Whether the fix was applied, when asked to fix.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fixed: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fix_error: Option<String>,
+}
+
+fn shim_doctor(out: &Output, fix: bool) {
+    let sunset = load_sunset(out);
+
+    let records: Vec<DoctorRecord> = sunset
+        .diagnose()
+        .into_iter()
+        .map(|finding| {
+            let fix_result = match (&finding.fix, fix) {
+                (Some(action), true) => Some(sunset.apply_fix(action)),
+                _ => None,
+            };
+
+            DoctorRecord {
+                fixed: fix_result.as_ref().map(|result| result.is_ok()),
+                fix_error: fix_result.and_then(|result| result.err().map(|err| err.to_string())),
+                finding,
+            }
+        })
+        .collect();
+
+    let unresolved_errors = records
+        .iter()
+        .any(|record| record.finding.severity == Severity::Error && record.fixed != Some(true));
+
+    if out.is_json() {
+        out.record(&records);
+    } else if records.is_empty() {
+        println!("No problems found");
+    } else {
+        for record in &records {
+            let severity = match record.finding.severity {
+                Severity::Info => "info",
+                Severity::Warning => "warning",
+                Severity::Error => "error",
+            };
+
+            let subject = match &record.finding.shim {
+                Some(name) => format!("[{}] ", name),
+                None => String::new(),
+            };
+
+            let status = match (&record.finding.fix, record.fixed, &record.fix_error) {
+                (_, Some(true), _) => String::from(" (fixed)"),
+                (_, Some(false), error) => {
+                    format!(" (fix failed: {})", error.as_deref().unwrap_or_default())
+                }
+                (Some(_), None, _) => String::from(" (fixable with --fix)"),
+                (None, _, _) => String::new(),
+            };
+
+            println!(
+                "{:7} {}{}{}",
+                severity, subject, record.finding.message, status
+            );
+        }
+    }
+
+    if unresolved_errors {
+        process::exit(-1);
+    }
+}
+
+fn shim_prune(out: &Output, dry_run: bool, yes: bool) {
+    let sunset = load_sunset(out);
+    let items = sunset
+        .prune_candidates()
+        .unwrap_or_else(|err| out.fail(err));
+
+    if !out.is_json() {
+        if items.is_empty() {
+            println!("Nothing to prune");
+            return;
+        }
+
+        println!("The following files will be removed:");
+
+        for item in &items {
+            let reason = match item.reason {
+                PruneReason::OrphanedExe => "executable without descriptor",
+                PruneReason::OrphanedDescriptor => "descriptor without executable",
+                PruneReason::MissingTarget => "target doesn't exist",
+            };
+
+            println!("  {} ({})", item.name, reason);
+            for file in &item.files {
+                println!("    {}", file.display());
+            }
+        }
+    }
+
+    if dry_run {
+        if out.is_json() {
+            out.record(&items);
+        }
+        return;
+    }
+
+    if !yes && !items.is_empty() {
+        if out.is_json() {
+            out.fail("Confirmation required, use --yes");
+        }
+
+        if !confirm(&format!("Remove {} shims?", items.len())) {
+            println!("Aborted");
+            return;
+        }
+    }
+
+    let results: Vec<BulkItem<PruneItem>> = items
+        .iter()
+        .map(|item| BulkItem::from_result(&item.name, sunset.prune(item).map(|_| item.clone())))
+        .collect();
+
+    if out.is_json() {
+        out.record(&results);
+    } else {
+        for result in &results {
+            match &result.error {
+                None => println!("Removed {}", result.name),
+                Some(error) => println!("Cannot remove {}: {}", result.name, error),
+            }
+        }
+    }
+
+    if results.iter().any(|result| !result.success) {
+        process::exit(-1);
+    }
+}
+
+fn shim_which(out: &Output, command: &str) {
+    let sunset = load_sunset(out);
+    let which = sunset.which(command).unwrap_or_else(|err| out.fail(err));
+
+    if out.is_json() {
+        out.record(&which);
+        return;
+    }
+
+    if which.chain.is_empty() {
+        println!("{}", which.target.display());
+        return;
+    }
+
+    for hop in &which.chain {
+        match &hop.project {
+            Some(project) if project.error.is_some() => println!(
+                "{} (shim {}, ignoring invalid {})",
+                hop.exe.display(),
+                hop.name,
+                project.file.display()
+            ),
+            Some(project) => println!(
+                "{} (shim {}, overridden by {})",
+                hop.exe.display(),
+                hop.name,
+                project.file.display()
+            ),
+            None => println!("{} (shim {})", hop.exe.display(), hop.name),
+        }
+    }
+
+    println!("=> {}", which.target.display());
+
+    if !which.args.is_empty() {
+        println!("args: {}", which.args.join(" "));
+    }
+
+    if !which.env.is_empty() {
+        println!("env:");
+        for item in &which.env {
+            match item.action {
+                ShimConfigEnvAction::Set => println!("  set {}={}", item.var, item.value),
+                ShimConfigEnvAction::Clear => println!("  clear {}", item.var),
+                ShimConfigEnvAction::Append => {
+                    println!(
+                        "  append {} += {:?}{}",
+                        item.var, item.separator, item.value
+                    )
+                }
+                ShimConfigEnvAction::Prepend => {
+                    println!(
+                        "  prepend {} += {}{:?}",
+                        item.var, item.value, item.separator
+                    )
+                }
+            }
+        }
+    }
+}
+
+fn shim_run(out: &Output, name: &str, args: &[String]) {
+    let sunset = load_sunset(out);
+    let info = sunset.info(name).unwrap_or_else(|err| out.fail(err));
+    let mut config =
+        shim::read_config_for(&info.shimfile, name).unwrap_or_else(|err| out.fail(err));
+    match shim::apply_project_override(&mut config, name) {
+        Ok(project) => {
+            if let Some(warning) = project.and_then(|it| it.warning()) {
+                eprintln!("sunset: {}", warning);
+            }
+        }
+        Err(err) => eprintln!("sunset: Ignoring project overrides: {}", err),
+    }
+
+    // The command line of sunset isn't the shim's, the raw one is rebuilt from the args.
+    let raw_tail = quoting::join_msvcrt(args);
+
+    let exit_code = shim::launch(&config, args, Some(&raw_tail), &sunset.interpreters)
+        .unwrap_or_else(|err| out.fail(format!("Failed to execute command {}", err)));
+
+    process::exit(exit_code);
+}
+
+#[derive(Serialize)]
+struct TrustRecord {
+    dir: path::PathBuf,
+    trusted: bool,
+    changed: bool,
+}
+
+fn shim_trust(out: &Output, dir: &Option<path::PathBuf>, trust: bool) {
+    let dir = dir.clone().unwrap_or_else(|| path::PathBuf::from("."));
+
+    let (dir, changed) = if trust {
+        project::trust(&dir)
+    } else {
+        project::untrust(&dir)
+    }
+    .unwrap_or_else(|err| out.fail(err));
+
+    if out.is_json() {
+        out.record(&TrustRecord {
+            dir,
+            trusted: trust,
+            changed,
+        });
+        return;
+    }
+
+    match (trust, changed) {
+        (true, true) => println!("Trusted {}", dir.display()),
+        (true, false) => println!("{} is already trusted", dir.display()),
+        (false, true) => println!("Untrusted {}", dir.display()),
+        (false, false) => println!("{} is not trusted", dir.display()),
+    }
+
+    if trust && !dir.join(project::PROJECT_FILE).is_file() {
+        println!("(there is no {} in it yet)", project::PROJECT_FILE);
+    }
+}
+
+fn shim_sync(out: &Output, manifest_path: &path::Path, check: bool, adopt: bool) {
+    let sunset = load_sunset(out);
+    let manifest = manifest::read_manifest(manifest_path).unwrap_or_else(|err| out.fail(err));
+    let plan = sunset
+        .sync_plan(&manifest, adopt)
+        .unwrap_or_else(|err| out.fail(err));
+
+    if !out.is_json() {
+        if plan.is_empty() {
+            println!("Shims match manifest {}", plan.manifest);
+            return;
+        }
+
+        for step in &plan.steps {
+            let reasons: Vec<&str> = step
+                .reasons
+                .iter()
+                .map(|reason| match reason {
+                    SyncReason::Missing => "missing",
+                    SyncReason::Descriptor => "descriptor differs",
+                    SyncReason::Template => "executable missing or outdated",
+                    SyncReason::NotInManifest => "not in manifest",
+                    SyncReason::NotManaged => "not managed by the manifest",
+                })
+                .collect();
+
+            let (sign, action) = match step.action {
+                SyncAction::Create => ('+', "create"),
+                SyncAction::Update => ('~', "update"),
+                SyncAction::Remove => ('-', "remove"),
+                SyncAction::Conflict => ('!', "conflict"),
+            };
+
+            println!(
+                "{} {} ({}: {})",
+                sign,
+                step.name,
+                action,
+                reasons.join(", ")
+            );
+        }
+    }
+
+    if check {
+        if out.is_json() {
+            out.record(&plan);
+        }
+
+        if !plan.is_empty() {
+            process::exit(1);
+        }
+        return;
+    }
+
+    let results: Vec<BulkItem<SyncStep>> = plan
+        .steps
+        .iter()
+        .map(|step| {
+            BulkItem::from_result(&step.name, sunset.sync_apply(step).map(|_| step.clone()))
+        })
+        .collect();
+
+    if out.is_json() {
+        out.record(&results);
+    } else {
+        for result in &results {
+            if let Some(error) = &result.error {
+                println!("Cannot sync {}: {}", result.name, error);
+            }
+        }
+
+        let applied = results.iter().filter(|result| result.success).count();
+        println!("Applied {} of {} changes", applied, results.len());
+    }
+
+    if results.iter().any(|result| !result.success) {
+        process::exit(-1);
     }
 }
@@ -0,0 +1,89 @@
+//! Presentation of command results as human-oriented text or JSON records.
+
+use clap::ValueEnum;
+use serde::Serialize;
+use std::fmt::Display;
+use std::process;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    Text,
+    Json,
+}
+
+pub struct Output {
+    pub format: Format,
+}
+
+impl Output {
+    pub fn is_json(&self) -> bool {
+        self.format == Format::Json
+    }
+
+    /// Prints `value` as a JSON record. Only meaningful in JSON mode.
+    pub fn record<T: Serialize>(&self, value: &T) {
+        match serde_json::to_string_pretty(value) {
+            Ok(json) => println!("{}", json),
+            Err(err) => self.fail(format!("Cannot serialize output: {}", err)),
+        }
+    }
+
+    /// Prints `message` in text mode. Ignored in JSON mode, where only records are printed.
+    pub fn text(&self, message: impl Display) {
+        if !self.is_json() {
+            println!("{}", message);
+        }
+    }
+
+    /// Prints `err` as text or as an `{"error": ...}` record, and exits.
+    pub fn fail(&self, err: impl Display) -> ! {
+        if self.is_json() {
+            let record = ErrorRecord {
+                error: err.to_string(),
+            };
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&record).unwrap_or_default()
+            );
+        } else {
+            println!("{}", err);
+        }
+
+        process::exit(-1);
+    }
+}
+
+#[derive(Serialize)]
+struct ErrorRecord {
+    error: String,
+}
+
+/// Outcome of one item of a bulk operation.
+#[derive(Serialize)]
+pub struct BulkItem<T: Serialize> {
+    pub name: String,
+    pub success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<T>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl<T: Serialize> BulkItem<T> {
+    pub fn from_result<E: Display>(name: &str, result: Result<T, E>) -> BulkItem<T> {
+        match result {
+            Ok(value) => BulkItem {
+                name: String::from(name),
+                success: true,
+                result: Some(value),
+                error: None,
+            },
+            Err(err) => BulkItem {
+                name: String::from(name),
+                success: false,
+                result: None,
+                error: Some(err.to_string()),
+            },
+        }
+    }
+}
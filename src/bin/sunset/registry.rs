@@ -0,0 +1,204 @@
+//! Access to the user environment variables stored in the registry, and PATH backups.
+
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Value of a user environment variable.
+///
+/// Whether the value is expandable (REG_EXPAND_SZ) is kept so writing it back doesn't
+/// turn it into a REG_SZ one, which would stop `%VAR%` entries from being expanded.
+#[derive(Debug, Clone)]
+pub struct EnvValue {
+    pub value: String,
+    pub expandable: bool,
+}
+
+#[cfg(windows)]
+mod imp {
+    use super::EnvValue;
+    use std::io;
+    use winreg::RegKey;
+    use winreg::enums::{HKEY_CURRENT_USER, KEY_ALL_ACCESS, RegType};
+    use winreg::types::{FromRegValue, ToRegValue};
+
+    fn open_environment_key() -> io::Result<RegKey> {
+        RegKey::predef(HKEY_CURRENT_USER).open_subkey_with_flags("Environment", KEY_ALL_ACCESS)
+    }
+
+    pub fn get_var(name: &str) -> io::Result<Option<EnvValue>> {
+        let raw_value = match open_environment_key()?.get_raw_value(name) {
+            Ok(raw_value) => raw_value,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+
+        Ok(Some(EnvValue {
+            value: String::from_reg_value(&raw_value)?,
+            expandable: raw_value.vtype == RegType::REG_EXPAND_SZ,
+        }))
+    }
+
+    pub fn set_var(name: &str, value: &EnvValue) -> io::Result<()> {
+        let mut raw_value = value.value.to_reg_value();
+        raw_value.vtype = if value.expandable {
+            RegType::REG_EXPAND_SZ
+        } else {
+            RegType::REG_SZ
+        };
+
+        open_environment_key()?.set_raw_value(name, &raw_value)
+    }
+
+    pub fn delete_var(name: &str) -> io::Result<()> {
+        match open_environment_key()?.delete_value(name) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        }
+    }
+}
+
+#[cfg(not(windows))]
+mod imp {
+    use super::EnvValue;
+    use std::io;
+
+    fn unsupported() -> io::Error {
+        io::Error::new(
+            io::ErrorKind::Unsupported,
+            "the user environment can only be modified on Windows",
+        )
+    }
+
+    pub fn get_var(_name: &str) -> io::Result<Option<EnvValue>> {
+        Err(unsupported())
+    }
+
+    pub fn set_var(_name: &str, _value: &EnvValue) -> io::Result<()> {
+        Err(unsupported())
+    }
+
+    pub fn delete_var(_name: &str) -> io::Result<()> {
+        Err(unsupported())
+    }
+}
+
+pub use imp::{delete_var, get_var, set_var};
+
+fn get_backups_dir() -> io::Result<PathBuf> {
+    match env::var("LOCALAPPDATA") {
+        Ok(value) => Ok(Path::new(&value).join("sunset").join("backups")),
+        Err(err) => Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("Failed to get value of LOCALAPPDATA: {}", err),
+        )),
+    }
+}
+
+/// Saves `value` as a timestamped PATH backup, returning the backup file.
+///
+/// Backups are named `PATH-<millis>.toml`, with a `-<n>` counter when that name is taken, so a
+/// backup never replaces another.
+pub fn write_path_backup(value: &EnvValue) -> io::Result<PathBuf> {
+    let backups_dir = get_backups_dir()?;
+    fs::create_dir_all(&backups_dir)?;
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+
+    let vtype = if value.expandable {
+        "REG_EXPAND_SZ"
+    } else {
+        "REG_SZ"
+    };
+
+    let mut content = toml::value::Table::new();
+    content.insert(
+        String::from("timestamp"),
+        toml::Value::from(now.as_secs() as i64),
+    );
+    content.insert(String::from("type"), toml::Value::from(vtype));
+    content.insert(
+        String::from("value"),
+        toml::Value::from(value.value.as_str()),
+    );
+
+    let toml_content =
+        toml::to_string(&content).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+    for counter in 0.. {
+        let file_name = match counter {
+            0 => format!("PATH-{}.toml", now.as_millis()),
+            _ => format!("PATH-{}-{}.toml", now.as_millis(), counter),
+        };
+        let backup_path = backups_dir.join(file_name);
+
+        let mut file = match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&backup_path)
+        {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err),
+        };
+
+        file.write_all(toml_content.as_bytes())?;
+
+        return Ok(backup_path);
+    }
+
+    unreachable!("there is always a free backup name")
+}
+
+/// Order of a backup file name, `PATH-<millis>` or `PATH-<millis>-<n>`.
+fn backup_order(path: &Path) -> Option<(u64, u64)> {
+    let stem = path.file_stem()?.to_str()?.strip_prefix("PATH-")?;
+
+    match stem.split_once('-') {
+        None => Some((stem.parse().ok()?, 0)),
+        Some((millis, counter)) => Some((millis.parse().ok()?, counter.parse().ok()?)),
+    }
+}
+
+/// Most recent PATH backup, if any.
+pub fn latest_path_backup() -> io::Result<Option<PathBuf>> {
+    let backups_dir = get_backups_dir()?;
+
+    if !backups_dir.is_dir() {
+        return Ok(None);
+    }
+
+    Ok(fs::read_dir(&backups_dir)?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter_map(|path| Some((backup_order(&path)?, path)))
+        .max_by_key(|(order, _)| *order)
+        .map(|(_, path)| path))
+}
+
+pub fn read_path_backup(backup_path: &Path) -> io::Result<EnvValue> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+
+    let content = fs::read_to_string(backup_path)?;
+    let table: toml::value::Table =
+        toml::from_str(&content).map_err(|err| invalid(err.to_string()))?;
+
+    let value = table
+        .get("value")
+        .and_then(|value| value.as_str())
+        .ok_or(invalid(String::from("missing value")))?;
+
+    let expandable = !matches!(
+        table.get("type").and_then(|value| value.as_str()),
+        Some("REG_SZ")
+    );
+
+    Ok(EnvValue {
+        value: String::from(value),
+        expandable,
+    })
+}
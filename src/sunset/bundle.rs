@@ -0,0 +1,452 @@
+//! Portable bundles of shim descriptors, to move shims between machines.
+
+use serde::Serialize;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml::value::Table;
+use toml_edit::DocumentMut;
+
+use crate::descriptor;
+use crate::error::{Result, SunsetError};
+use crate::shim::{self, ExpandSyntax};
+use crate::shimmer::{self, Sunset};
+
+/// Version of the bundle format written by `export`.
+pub const BUNDLE_VERSION: i64 = 1;
+
+/// Variables tried when rewriting absolute paths, the one with the longest matching value wins.
+const PATH_VARIABLES: [&str; 6] = [
+    "LOCALAPPDATA",
+    "APPDATA",
+    "ProgramData",
+    "ProgramFiles",
+    "USERPROFILE",
+    "HOME",
+];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Bundle {
+    pub version: i64,
+    pub shims: Vec<BundledShim>,
+    /// Descriptors under `templates` extended by the shims.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub templates: Vec<BundledShim>,
+}
+
+/// A shim or a template in a bundle: its name and the raw content of its descriptor.
+#[derive(Debug, Clone, Serialize)]
+pub struct BundledShim {
+    pub name: String,
+    pub descriptor: String,
+}
+
+/// What to do when an imported shim already exists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictPolicy {
+    Skip,
+    Overwrite,
+    Rename,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportOutcome {
+    Created,
+    Overwritten,
+    Renamed,
+    Skipped,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportedShim {
+    /// Name in the bundle.
+    pub name: String,
+    /// Name of the shim created, which differs from `name` when renamed.
+    pub shim_name: String,
+    pub outcome: ImportOutcome,
+}
+
+impl Bundle {
+    pub fn to_toml(&self) -> Result<String> {
+        toml::to_string(self).map_err(|err| SunsetError::Setting(err.to_string()))
+    }
+}
+
+/// Reads a bundle file.
+pub fn read_bundle(path: &Path) -> Result<Bundle> {
+    let content = fs::read_to_string(path).map_err(|err| SunsetError::io(path, err))?;
+
+    parse_bundle(&content).map_err(|message| SunsetError::InvalidBundle {
+        path: path.to_path_buf(),
+        message,
+    })
+}
+
+/// Parses the content of a bundle file.
+pub fn parse_bundle(content: &str) -> std::result::Result<Bundle, String> {
+    let value: Table = toml::from_str(content).map_err(|err| err.to_string())?;
+
+    let version = match value.get("version") {
+        None => BUNDLE_VERSION,
+        Some(version) => version.as_integer().ok_or("version must be an integer")?,
+    };
+
+    if version > BUNDLE_VERSION {
+        return Err(format!(
+            "version {} is not supported, upgrade sunset to import it",
+            version
+        ));
+    }
+
+    let shims = parse_descriptors(&value, "shims")?;
+    let templates = parse_descriptors(&value, "templates")?;
+
+    Ok(Bundle {
+        version,
+        shims,
+        templates,
+    })
+}
+
+fn parse_descriptors(value: &Table, key: &str) -> std::result::Result<Vec<BundledShim>, String> {
+    match value.get(key) {
+        None => Ok(Vec::new()),
+        Some(items) => items
+            .as_array()
+            .ok_or(format!("{} must be an array", key))?
+            .iter()
+            .map(|it| {
+                let table = it
+                    .as_table()
+                    .ok_or(format!("{} items must be tables", key))?;
+                let name = table
+                    .get("name")
+                    .and_then(|name| name.as_str())
+                    .ok_or("shim without name")?;
+
+                if !is_plain_name(name) {
+                    return Err(format!("{} has an invalid name {:?}", key, name));
+                }
+
+                let descriptor = table
+                    .get("descriptor")
+                    .and_then(|descriptor| descriptor.as_str())
+                    .ok_or(format!("shim {} doesn't have a descriptor", name))?;
+
+                Ok(BundledShim {
+                    name: String::from(name),
+                    descriptor: String::from(descriptor),
+                })
+            })
+            .collect(),
+    }
+}
+
+/// Whether `name` can be joined to a directory without leaving it: not empty, without
+/// separators, `..`, or a drive or root prefix.
+fn is_plain_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.contains(['/', '\\', ':'])
+        && !name.contains("..")
+        && Path::new(name)
+            .components()
+            .all(|it| matches!(it, std::path::Component::Normal(_)))
+}
+
+/// Rewrites `path` to start with a reference to one of `PATH_VARIABLES`, if it's under one.
+pub fn rewrite_path(path: &str, syntax: ExpandSyntax) -> Option<String> {
+    PATH_VARIABLES
+        .iter()
+        .filter_map(|var| {
+            let value = env::var(var).ok().filter(|value| !value.is_empty())?;
+            let rest = strip_dir_prefix(path, value.trim_end_matches(['\\', '/']))?;
+            Some((value.len(), var, rest))
+        })
+        .max_by_key(|(len, _, _)| *len)
+        .map(|(_, var, rest)| match syntax {
+            ExpandSyntax::Windows => format!("%{}%{}", var, rest),
+            ExpandSyntax::Unix => format!("${{{}}}{}", var, rest),
+        })
+}
+
+/// The rest of `path` after the directory `dir`, compared like the OS does.
+fn strip_dir_prefix<'a>(path: &'a str, dir: &str) -> Option<&'a str> {
+    let head = path.get(..dir.len())?;
+
+    let same = if cfg!(windows) {
+        head.eq_ignore_ascii_case(dir)
+    } else {
+        head == dir
+    };
+
+    let rest = &path[dir.len()..];
+
+    (same && (rest.is_empty() || rest.starts_with(['\\', '/']))).then_some(rest)
+}
+
+/// Rewrites the absolute `path` and `cwd` of a descriptor to use variables, enabling
+/// `env_expand_path` so the shim expands them.
+///
+/// Descriptors whose paths already contain variable-like characters, descriptors with
+/// variants or `extends`, and aliases are left as they are.
+fn rewrite_descriptor(content: &str) -> std::result::Result<String, String> {
+    let value: Table = toml::from_str(content).map_err(|err| err.to_string())?;
+
+    if value.contains_key("extends") || shim::alias_of(content).is_some() {
+        return Ok(content.to_string());
+    }
+
+    let config = shim::parse_config(content)?;
+
+    if !config.variants.is_empty() {
+        return Ok(content.to_string());
+    }
+
+    let mut doc: DocumentMut = content
+        .parse()
+        .map_err(|err: toml_edit::TomlError| err.to_string())?;
+
+    let has_references = |value: &str| value.contains(['%', '$']);
+
+    if !config.env_expand_path
+        && (has_references(&config.path) || config.cwd.as_deref().is_some_and(has_references))
+    {
+        return Ok(content.to_string());
+    }
+
+    let mut rewritten = false;
+
+    for key in ["path", "cwd"] {
+        let value = match key {
+            "path" => Some(config.path.as_str()),
+            _ => config.cwd.as_deref(),
+        };
+
+        if let Some(new_value) = value.and_then(|it| rewrite_path(it, config.env_expand_syntax)) {
+            descriptor::set_key(&mut doc, key, &new_value).map_err(|err| err.to_string())?;
+            rewritten = true;
+        }
+    }
+
+    if rewritten && !config.env_expand_path {
+        descriptor::set_key(&mut doc, "env_expand_path", "true").map_err(|err| err.to_string())?;
+    }
+
+    Ok(doc.to_string())
+}
+
+impl Sunset {
+    /// Bundles the descriptors of the named shims, or of every shim if `names` is empty, with
+    /// the templates they extend.
+    ///
+    /// With `rewrite_paths`, absolute paths under well-known directories like
+    /// `%LOCALAPPDATA%` are rewritten to reference them.
+    pub fn export(&self, names: &[String], rewrite_paths: bool) -> Result<Bundle> {
+        let names = if names.is_empty() {
+            self.list()?
+        } else {
+            names.to_vec()
+        };
+
+        let shims = names
+            .iter()
+            .map(|name| {
+                let info = self.info(name)?;
+
+                let descriptor = if rewrite_paths {
+                    rewrite_descriptor(&info.content).map_err(|message| {
+                        SunsetError::InvalidDescriptor {
+                            path: info.shimfile.clone(),
+                            message,
+                        }
+                    })?
+                } else {
+                    info.content
+                };
+
+                Ok(BundledShim {
+                    name: name.clone(),
+                    descriptor,
+                })
+            })
+            .collect::<Result<Vec<BundledShim>>>()?;
+
+        let templates = self.extended_templates(&shims)?;
+
+        Ok(Bundle {
+            version: BUNDLE_VERSION,
+            shims,
+            templates,
+        })
+    }
+
+    /// Templates extended by `shims`, directly or through other templates.
+    fn extended_templates(&self, shims: &[BundledShim]) -> Result<Vec<BundledShim>> {
+        let mut templates: Vec<BundledShim> = Vec::new();
+        let mut pending: Vec<String> = shims
+            .iter()
+            .flat_map(|shim| shim::extends_of(&shim.descriptor))
+            .collect();
+
+        while let Some(name) = pending.pop() {
+            let path = self.template_file(&name);
+
+            if !path.is_file() || templates.iter().any(|it| it.name == name) {
+                continue;
+            }
+
+            let descriptor =
+                fs::read_to_string(&path).map_err(|err| SunsetError::io(&path, err))?;
+
+            pending.extend(shim::extends_of(&descriptor));
+            templates.push(BundledShim { name, descriptor });
+        }
+
+        Ok(templates)
+    }
+
+    fn template_file(&self, name: &str) -> PathBuf {
+        self.shims_dir
+            .join(shim::TEMPLATES_DIR)
+            .join(format!("{}.shim", name))
+    }
+
+    /// Writes a bundled template, before the shims extending it are imported.
+    ///
+    /// Descriptors refer to templates by name, so an existing template with other content is
+    /// only replaced with `ConflictPolicy::Overwrite`, and never renamed.
+    pub fn import_template(
+        &self,
+        template: &BundledShim,
+        policy: ConflictPolicy,
+    ) -> Result<ImportedShim> {
+        let path = self.template_file(&template.name);
+        let shim_name = format!("{}/{}", shim::TEMPLATES_DIR, template.name);
+
+        let outcome = match fs::read_to_string(&path) {
+            Err(_) => ImportOutcome::Created,
+            Ok(current) if current == template.descriptor => ImportOutcome::Skipped,
+            Ok(_) if policy == ConflictPolicy::Overwrite => ImportOutcome::Overwritten,
+            Ok(_) => ImportOutcome::Skipped,
+        };
+
+        if outcome != ImportOutcome::Skipped {
+            toml::from_str::<Table>(&template.descriptor).map_err(|err| {
+                SunsetError::InvalidDescriptor {
+                    path: path.clone(),
+                    message: err.to_string(),
+                }
+            })?;
+
+            let dir = self.shims_dir.join(shim::TEMPLATES_DIR);
+            fs::create_dir_all(&dir).map_err(|err| SunsetError::io(&dir, err))?;
+
+            shimmer::write_atomically(&path, &template.descriptor)?;
+        }
+
+        Ok(ImportedShim {
+            name: shim_name.clone(),
+            shim_name,
+            outcome,
+        })
+    }
+
+    /// Writes the descriptor of a bundled shim and links its executable.
+    pub fn import(&self, shim: &BundledShim, policy: ConflictPolicy) -> Result<ImportedShim> {
+        let exists = self.shimfile(&shim.name).exists();
+
+        let (shim_name, outcome) = match (exists, policy) {
+            (false, _) => (shim.name.clone(), ImportOutcome::Created),
+            (true, ConflictPolicy::Skip) => {
+                return Ok(ImportedShim {
+                    name: shim.name.clone(),
+                    shim_name: shim.name.clone(),
+                    outcome: ImportOutcome::Skipped,
+                });
+            }
+            (true, ConflictPolicy::Overwrite) => (shim.name.clone(), ImportOutcome::Overwritten),
+            (true, ConflictPolicy::Rename) => (self.free_name(&shim.name), ImportOutcome::Renamed),
+        };
+
+        // An alias takes `win` from the shim it refers to, which must exist already.
+        let config = shim::parse_config_at(&self.shimfile(&shim_name), &shim.descriptor, None)?;
+
+        fs::create_dir_all(&self.shims_dir).map_err(|err| SunsetError::io(&self.shims_dir, err))?;
+
+        shimmer::write_atomically(&self.shimfile(&shim_name), &shim.descriptor)?;
+
+        // Disabled shims have no executable, like after `disable`.
+        let exe = self.shimmed_exe(&shim_name);
+
+        if !config.disabled {
+            shimmer::shim_create(&self.shim_exe(config.win), &exe)?;
+        } else if exe.exists() {
+            fs::remove_file(&exe).map_err(|err| SunsetError::io(&exe, err))?;
+        }
+
+        Ok(ImportedShim {
+            name: shim.name.clone(),
+            shim_name,
+            outcome,
+        })
+    }
+
+    /// First of `name-2`, `name-3`, ... without a descriptor.
+    fn free_name(&self, name: &str) -> String {
+        (2..)
+            .map(|n| format!("{}-{}", name, n))
+            .find(|candidate| !self.shimfile(candidate).exists())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bundle_with(key: &str, name: &str) -> String {
+        format!(
+            "version = 1\n\n[[{}]]\nname = {:?}\ndescriptor = 'path = \"C:\\\\a.exe\"'\n",
+            key, name
+        )
+    }
+
+    #[test]
+    fn rejects_names_leaving_the_directory() {
+        let names = [
+            "",
+            "..",
+            "../escaped",
+            "a/b",
+            r"a\b",
+            r"..\escaped",
+            "/abs",
+            r"\abs",
+            r"C:\abs",
+            "C:abs",
+            "a..b",
+        ];
+
+        for key in ["shims", "templates"] {
+            for name in names {
+                assert!(
+                    parse_bundle(&bundle_with(key, name)).is_err(),
+                    "accepted {} name {:?}",
+                    key,
+                    name
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn accepts_plain_names() {
+        for name in ["node", "node-2", "python3.12", "my_tool"] {
+            let bundle = parse_bundle(&bundle_with("shims", name)).unwrap();
+
+            assert_eq!(bundle.shims[0].name, name);
+        }
+    }
+}
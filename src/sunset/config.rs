@@ -0,0 +1,151 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::path::{Path, PathBuf};
+use toml::value::Table;
+
+use crate::error::{Result, SunsetError};
+use crate::interpreter;
+use crate::shim::ExpandSyntax;
+
+/// Sunset's own configuration, read from `sunset.toml`.
+///
+/// Every setting is optional; `Sunset::from_config` applies the precedence
+/// environment variable, this file and finally the built-in default.
+#[derive(Debug, Default)]
+pub struct SunsetConfig {
+    pub shims_path: Option<PathBuf>,
+    pub templates_path: Option<PathBuf>,
+    pub defaults: ShimDefaults,
+    /// Interpreters for script targets by lowercase extension, over the built-in ones.
+    pub interpreters: BTreeMap<String, Vec<String>>,
+}
+
+/// Defaults applied to newly created shims when not given in the command line.
+#[derive(Debug)]
+pub struct ShimDefaults {
+    pub win: bool,
+    pub hidden: bool,
+    pub wait: bool,
+    pub env_expand_path: bool,
+    pub env_expand_args: bool,
+    pub env_expand_syntax: ExpandSyntax,
+}
+
+impl Default for ShimDefaults {
+    fn default() -> Self {
+        ShimDefaults {
+            win: false,
+            hidden: false,
+            wait: true,
+            env_expand_path: false,
+            env_expand_args: false,
+            env_expand_syntax: ExpandSyntax::Windows,
+        }
+    }
+}
+
+/// Loads the configuration from the default location.
+pub fn load() -> Result<SunsetConfig> {
+    read_config(&get_config_file())
+}
+
+/// Location of `sunset.toml`: `SUNSET_CONFIG` if set, otherwise the platform config directory
+/// (`%APPDATA%\sunset` on Windows, `$XDG_CONFIG_HOME/sunset` or `~/.config/sunset` elsewhere).
+pub fn get_config_file() -> PathBuf {
+    if let Ok(value) = env::var("SUNSET_CONFIG") {
+        return PathBuf::from(value);
+    }
+
+    let config_dir = if cfg!(windows) {
+        env::var("APPDATA").map(PathBuf::from).unwrap_or_default()
+    } else {
+        match env::var("XDG_CONFIG_HOME") {
+            Ok(value) => PathBuf::from(value),
+            Err(_) => env::var("HOME")
+                .map(|home| PathBuf::from(home).join(".config"))
+                .unwrap_or_default(),
+        }
+    };
+
+    config_dir.join("sunset").join("sunset.toml")
+}
+
+/// Reads a configuration file. A missing file yields the default configuration.
+pub fn read_config(path: &Path) -> Result<SunsetConfig> {
+    if !path.exists() {
+        return Ok(SunsetConfig::default());
+    }
+
+    let content = std::fs::read_to_string(path).map_err(|err| SunsetError::io(path, err))?;
+
+    parse_config(&content).map_err(|message| SunsetError::InvalidConfig {
+        path: path.to_path_buf(),
+        message,
+    })
+}
+
+/// Parses the content of a configuration file.
+pub fn parse_config(content: &str) -> std::result::Result<SunsetConfig, String> {
+    let value: Table = toml::from_str(content).map_err(|err| err.to_string())?;
+
+    let shims_path = get_str(&value, "shims_path")?.map(PathBuf::from);
+    let templates_path = get_str(&value, "templates_path")?.map(PathBuf::from);
+
+    let mut defaults = ShimDefaults::default();
+
+    if let Some(defaults_raw) = value.get("defaults") {
+        let table = defaults_raw.as_table().ok_or("defaults must be a table")?;
+
+        defaults.win = get_bool(table, "win")?.unwrap_or(defaults.win);
+        defaults.hidden = get_bool(table, "hidden")?.unwrap_or(defaults.hidden);
+        defaults.wait = get_bool(table, "wait")?.unwrap_or(defaults.wait);
+        defaults.env_expand_path =
+            get_bool(table, "env_expand_path")?.unwrap_or(defaults.env_expand_path);
+        defaults.env_expand_args =
+            get_bool(table, "env_expand_args")?.unwrap_or(defaults.env_expand_args);
+
+        if let Some(syntax) = get_str(table, "env_expand_syntax")? {
+            defaults.env_expand_syntax = ExpandSyntax::from_name(syntax)
+                .ok_or(format!("{} is not a valid env_expand_syntax", syntax))?;
+        }
+    }
+
+    let interpreters = match value.get("interpreters") {
+        None => BTreeMap::new(),
+        Some(interpreters_raw) => interpreter::parse_interpreters(
+            interpreters_raw
+                .as_table()
+                .ok_or("interpreters must be a table")?,
+        )?,
+    };
+
+    Ok(SunsetConfig {
+        shims_path,
+        templates_path,
+        defaults,
+        interpreters,
+    })
+}
+
+pub(crate) fn get_str<'a>(
+    table: &'a Table,
+    key: &str,
+) -> std::result::Result<Option<&'a str>, String> {
+    match table.get(key) {
+        None => Ok(None),
+        Some(value) => value
+            .as_str()
+            .map(Some)
+            .ok_or(format!("{} must be a string", key)),
+    }
+}
+
+pub(crate) fn get_bool(table: &Table, key: &str) -> std::result::Result<Option<bool>, String> {
+    match table.get(key) {
+        None => Ok(None),
+        Some(value) => value
+            .as_bool()
+            .map(Some)
+            .ok_or(format!("{} must be a boolean", key)),
+    }
+}
@@ -0,0 +1,356 @@
+//! Edits of shim descriptors that keep their comments and formatting.
+
+use std::fs;
+use toml_edit::{Array, ArrayOfTables, DocumentMut, InlineTable, Item, Table, Value, value};
+
+use crate::error::{Result, SunsetError};
+use crate::quoting::Quoting;
+use crate::shim::{ExpandSyntax, ShimConfig, ShimConfigEnvAction, ShimConfigEnvActionItem};
+use crate::shimmer::Sunset;
+
+/// Scalar descriptor keys that can be set and unset.
+pub const SCALAR_KEYS: [&str; 16] = [
+    "path",
+    "target_shim",
+    "interpreter",
+    "active",
+    "win",
+    "hidden",
+    "wait",
+    "disabled",
+    "cwd",
+    "group",
+    "env_expand_path",
+    "env_expand_args",
+    "env_expand_syntax",
+    "response_files",
+    "spill_args",
+    "quoting",
+];
+
+fn invalid(message: String) -> SunsetError {
+    SunsetError::InvalidArgument(message)
+}
+
+/// Sets a scalar key, converting `raw_value` to the type of the key.
+pub fn set_key(doc: &mut DocumentMut, key: &str, raw_value: &str) -> Result<()> {
+    let item = match key {
+        "path" | "target_shim" if raw_value.trim().is_empty() => {
+            return Err(invalid(format!("{} cannot be empty", key)));
+        }
+        "path" | "target_shim" | "interpreter" | "active" | "cwd" | "group" => value(raw_value),
+        "win" | "hidden" | "wait" | "disabled" | "env_expand_path" | "env_expand_args"
+        | "response_files" => match raw_value.to_lowercase().as_str() {
+            "true" => value(true),
+            "false" => value(false),
+            _ => return Err(invalid(format!("{} must be true or false", key))),
+        },
+        "spill_args" => match (raw_value.to_lowercase().as_str(), raw_value.parse::<i64>()) {
+            ("true", _) => value(true),
+            ("false", _) => value(false),
+            (_, Ok(limit)) if limit > 0 => value(limit),
+            _ => {
+                return Err(invalid(format!(
+                    "{} must be true, false or a number of characters",
+                    key
+                )));
+            }
+        },
+        "quoting" => match Quoting::from_name(raw_value) {
+            Some(quoting) => value(quoting.name()),
+            None => return Err(invalid(format!("{} must be msvcrt, cmd or raw", key))),
+        },
+        "env_expand_syntax" => match ExpandSyntax::from_name(raw_value) {
+            Some(syntax) => value(syntax.name()),
+            None => return Err(invalid(format!("{} must be windows or unix", key))),
+        },
+        _ => return Err(unknown_key(key)),
+    };
+
+    // Replacing only the value keeps the key decoration (comments, spacing).
+    match doc.get_mut(key).and_then(|current| current.as_value_mut()) {
+        Some(current) => {
+            let decor = current.decor().clone();
+            *current = item
+                .into_value()
+                .unwrap_or_else(|item| unreachable!("scalar items are values: {:?}", item));
+            *current.decor_mut() = decor;
+        }
+        None => {
+            doc.insert(key, item);
+        }
+    }
+
+    Ok(())
+}
+
+/// Removes a scalar key, so the shim uses its default.
+pub fn unset_key(doc: &mut DocumentMut, key: &str) -> Result<()> {
+    if key == "path" {
+        return Err(invalid(String::from(
+            "path is required and cannot be unset",
+        )));
+    }
+
+    if !SCALAR_KEYS.contains(&key) {
+        return Err(unknown_key(key));
+    }
+
+    doc.remove(key);
+
+    Ok(())
+}
+
+/// Replaces `name` with `new_name` in `extends`, either a string or an array of strings.
+pub fn rename_extends(doc: &mut DocumentMut, name: &str, new_name: &str) -> Result<()> {
+    let Some(extends) = doc.get_mut("extends").and_then(|it| it.as_value_mut()) else {
+        return Ok(());
+    };
+
+    let values: Vec<&mut Value> = match extends {
+        Value::Array(array) => array.iter_mut().collect(),
+        other => vec![other],
+    };
+
+    for current in values {
+        if current.as_str() == Some(name) {
+            let decor = current.decor().clone();
+            *current = Value::from(new_name);
+            *current.decor_mut() = decor;
+        }
+    }
+
+    Ok(())
+}
+
+fn unknown_key(key: &str) -> SunsetError {
+    invalid(format!(
+        "{} is not a valid key, use one of: {}",
+        key,
+        SCALAR_KEYS.join(", ")
+    ))
+}
+
+fn args_array(doc: &mut DocumentMut) -> Result<&mut Array> {
+    if doc.get("args").is_none() {
+        doc.insert("args", value(Array::new()));
+    }
+
+    doc.get_mut("args")
+        .and_then(|args| args.as_array_mut())
+        .ok_or(invalid(String::from("args is not an array")))
+}
+
+pub fn add_args(doc: &mut DocumentMut, args: &[String]) -> Result<()> {
+    let array = args_array(doc)?;
+
+    for arg in args {
+        array.push(arg.as_str());
+    }
+
+    Ok(())
+}
+
+/// Removes every occurrence of each of `args`.
+pub fn remove_args(doc: &mut DocumentMut, args: &[String]) -> Result<()> {
+    let array = args_array(doc)?;
+
+    array.retain(|arg| {
+        !arg.as_str()
+            .is_some_and(|arg| args.iter().any(|it| it == arg))
+    });
+
+    // The first element carries no leading space, as written by `to_string`.
+    if let Some(first) = array.get_mut(0) {
+        first.decor_mut().set_prefix("");
+    }
+
+    Ok(())
+}
+
+pub fn clear_args(doc: &mut DocumentMut) -> Result<()> {
+    args_array(doc)?.clear();
+
+    Ok(())
+}
+
+fn env_table(item: &ShimConfigEnvActionItem) -> Table {
+    let mut table = Table::new();
+    table.insert("var", value(item.var.as_str()));
+
+    if item.action != ShimConfigEnvAction::Set {
+        table.insert("action", value(item.action.name()));
+    }
+
+    if item.action != ShimConfigEnvAction::Clear {
+        table.insert("value", value(item.value.as_str()));
+    }
+
+    if !item.separator.is_empty() {
+        table.insert("separator", value(item.separator.as_str()));
+    }
+
+    table
+}
+
+/// Appends an env action, as `[[env]]` or to an inline `env = [...]` array.
+pub fn add_env(doc: &mut DocumentMut, item: &ShimConfigEnvActionItem) -> Result<()> {
+    let table = env_table(item);
+
+    match doc.get_mut("env") {
+        None => {
+            let mut array = ArrayOfTables::new();
+            array.push(table);
+            doc.insert("env", Item::ArrayOfTables(array));
+        }
+        Some(Item::ArrayOfTables(array)) => array.push(table),
+        Some(Item::Value(Value::Array(array))) => {
+            let inline: InlineTable = table.into_inline_table();
+            array.push(inline);
+        }
+        Some(_) => return Err(invalid(String::from("env is not an array"))),
+    }
+
+    Ok(())
+}
+
+/// Removes every env action for `var`, returning how many were removed.
+pub fn remove_env(doc: &mut DocumentMut, var: &str) -> Result<usize> {
+    let is_var = |candidate: Option<&Item>| candidate.and_then(|it| it.as_str()) == Some(var);
+
+    let removed = match doc.get_mut("env") {
+        None => 0,
+        Some(Item::ArrayOfTables(array)) => {
+            let before = array.len();
+            array.retain(|table| !is_var(table.get("var")));
+            before - array.len()
+        }
+        Some(Item::Value(Value::Array(array))) => {
+            let before = array.len();
+            array.retain(|it| {
+                let candidate = it.as_inline_table().and_then(|table| table.get("var"));
+                candidate.and_then(|it| it.as_str()) != Some(var)
+            });
+            before - array.len()
+        }
+        Some(_) => return Err(invalid(String::from("env is not an array"))),
+    };
+
+    Ok(removed)
+}
+
+impl Sunset {
+    /// Applies `edit` to the descriptor of a shim, keeping comments and formatting.
+    ///
+    /// The result is validated and written like `update_descriptor` does.
+    pub fn edit_descriptor<F>(&self, name: &str, edit: F) -> Result<ShimConfig>
+    where
+        F: FnOnce(&mut DocumentMut) -> Result<()>,
+    {
+        let shimfile = self.shimfile(name);
+
+        if !shimfile.exists() {
+            return Err(SunsetError::ShimNotFound(String::from(name)));
+        }
+
+        let content =
+            fs::read_to_string(&shimfile).map_err(|err| SunsetError::io(&shimfile, err))?;
+
+        let mut doc: DocumentMut = content.parse().map_err(|err: toml_edit::TomlError| {
+            SunsetError::InvalidDescriptor {
+                path: shimfile.clone(),
+                message: err.to_string(),
+            }
+        })?;
+
+        edit(&mut doc)?;
+
+        self.update_descriptor(name, &doc.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DESCRIPTOR: &str = r#"# Node for the web project
+path = 'C:\node\node.exe' # pinned version
+wait = true
+
+# Flags for every run
+args = ["--max-old-space-size=4096", "--trace-warnings"]
+
+[[env]]
+var = "NODE_ENV" # used by the build
+value = "development"
+"#;
+
+    fn edited(edit: impl FnOnce(&mut DocumentMut) -> Result<()>) -> String {
+        let mut doc: DocumentMut = DESCRIPTOR.parse().unwrap();
+        edit(&mut doc).unwrap();
+        doc.to_string()
+    }
+
+    #[test]
+    fn set_key_keeps_comments_and_order() {
+        let content = edited(|doc| set_key(doc, "path", r"D:\node\node.exe"));
+
+        assert_eq!(
+            content,
+            DESCRIPTOR.replace(r"C:\node\node.exe", r"D:\node\node.exe")
+        );
+
+        let content = edited(|doc| set_key(doc, "hidden", "true"));
+
+        assert_eq!(
+            content,
+            DESCRIPTOR.replace("\n\n[[env]]", "\nhidden = true\n\n[[env]]")
+        );
+    }
+
+    #[test]
+    fn unset_key_keeps_the_other_keys() {
+        let content = edited(|doc| unset_key(doc, "wait"));
+
+        assert_eq!(content, DESCRIPTOR.replace("wait = true\n", ""));
+    }
+
+    #[test]
+    fn args_edits_keep_comments() {
+        let content = edited(|doc| add_args(doc, &[String::from("--inspect")]));
+
+        assert_eq!(
+            content,
+            DESCRIPTOR.replace(
+                r#""--trace-warnings"]"#,
+                r#""--trace-warnings", "--inspect"]"#
+            )
+        );
+
+        let content = edited(|doc| remove_args(doc, &[String::from("--max-old-space-size=4096")]));
+
+        assert_eq!(
+            content,
+            DESCRIPTOR.replace(r#""--max-old-space-size=4096", "#, "")
+        );
+    }
+
+    #[test]
+    fn env_edits_keep_comments() {
+        let item = ShimConfigEnvActionItem {
+            var: String::from("PATH"),
+            action: ShimConfigEnvAction::Prepend,
+            value: String::from(r"C:\node"),
+            separator: String::from(";"),
+        };
+
+        let content = edited(|doc| add_env(doc, &item));
+
+        assert!(content.starts_with(DESCRIPTOR));
+        assert!(content.contains("var = \"PATH\""));
+
+        let content = edited(|doc| remove_env(doc, "NODE_ENV").map(|_| ()));
+
+        assert_eq!(content, DESCRIPTOR.split("\n[[env]]").next().unwrap());
+    }
+}
@@ -0,0 +1,248 @@
+use serde::Serialize;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::error::{Result, SunsetError};
+use crate::search;
+use crate::shimmer::{ExeState, Sunset};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+/// Action that solves a finding.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Fix {
+    /// Create the shims directory.
+    CreateShimsDir { path: PathBuf },
+    /// Relink the executable of a shim to the current template.
+    Upgrade { name: String },
+    /// Remove an executable without descriptor.
+    RemoveExe { path: PathBuf },
+}
+
+/// A problem, or a remark, found in the sunset installation.
+#[derive(Debug, Clone, Serialize)]
+pub struct Finding {
+    pub severity: Severity,
+    /// Shim the finding is about, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shim: Option<String>,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fix: Option<Fix>,
+}
+
+impl Finding {
+    fn new(severity: Severity, message: String) -> Finding {
+        Finding {
+            severity,
+            shim: None,
+            message,
+            fix: None,
+        }
+    }
+
+    fn for_shim(severity: Severity, shim: &str, message: String) -> Finding {
+        Finding {
+            severity,
+            shim: Some(String::from(shim)),
+            message,
+            fix: None,
+        }
+    }
+
+    fn with_fix(mut self, fix: Fix) -> Finding {
+        self.fix = Some(fix);
+        self
+    }
+}
+
+impl Sunset {
+    /// Checks the environment, the templates and every shim.
+    pub fn diagnose(&self) -> Vec<Finding> {
+        let mut findings = Vec::new();
+
+        self.diagnose_environment(&mut findings);
+        self.diagnose_templates(&mut findings);
+
+        if self.shims_dir.is_dir() {
+            self.diagnose_shims(&mut findings);
+        }
+
+        findings
+    }
+
+    /// Applies the fix of a finding.
+    pub fn apply_fix(&self, fix: &Fix) -> Result<()> {
+        match fix {
+            Fix::CreateShimsDir { path } => {
+                fs::create_dir_all(path).map_err(|err| SunsetError::io(path, err))
+            }
+            Fix::Upgrade { name } => self.upgrade(name).map(|_| ()),
+            Fix::RemoveExe { path } => {
+                fs::remove_file(path).map_err(|err| SunsetError::io(path, err))
+            }
+        }
+    }
+
+    fn diagnose_environment(&self, findings: &mut Vec<Finding>) {
+        if env::var("SUNSET_SHIMS_PATH").is_err() {
+            findings.push(Finding::new(
+                Severity::Warning,
+                String::from("SUNSET_SHIMS_PATH is not set; run `sunset init`"),
+            ));
+        }
+
+        if !self.shims_dir.is_dir() {
+            findings.push(
+                Finding::new(
+                    Severity::Error,
+                    format!("Shims directory {} doesn't exist", self.shims_dir.display()),
+                )
+                .with_fix(Fix::CreateShimsDir {
+                    path: self.shims_dir.clone(),
+                }),
+            );
+        }
+
+        let on_path = search::path_dirs()
+            .iter()
+            .any(|dir| search::is_same_dir(dir, &self.shims_dir));
+
+        if !on_path {
+            findings.push(Finding::new(
+                Severity::Error,
+                format!(
+                    "Shims directory {} is not on PATH; run `sunset init`",
+                    self.shims_dir.display()
+                ),
+            ));
+        }
+    }
+
+    fn diagnose_templates(&self, findings: &mut Vec<Finding>) {
+        for win in [false, true] {
+            let template = self.shim_exe(win);
+
+            if !template.is_file() {
+                findings.push(Finding::new(
+                    Severity::Error,
+                    format!("Shim template {} doesn't exist", template.display()),
+                ));
+            }
+        }
+    }
+
+    fn diagnose_shims(&self, findings: &mut Vec<Finding>) {
+        let statuses = match self.list_status() {
+            Ok(statuses) => statuses,
+            Err(err) => {
+                findings.push(Finding::new(Severity::Error, err.to_string()));
+                return;
+            }
+        };
+
+        let path_dirs = search::path_dirs();
+
+        for status in &statuses {
+            let name = status.name.as_str();
+
+            if let Some(error) = &status.error {
+                findings.push(Finding::for_shim(Severity::Error, name, error.clone()));
+                continue;
+            }
+
+            if !status.target_exists {
+                findings.push(Finding::for_shim(
+                    Severity::Error,
+                    name,
+                    format!(
+                        "Target {} doesn't exist",
+                        status.target.as_deref().unwrap_or_default()
+                    ),
+                ));
+            }
+
+            let upgrade = Fix::Upgrade {
+                name: String::from(name),
+            };
+
+            match status.exe_state {
+                ExeState::Current | ExeState::Unknown | ExeState::Disabled => {}
+                ExeState::Missing => findings.push(
+                    Finding::for_shim(
+                        Severity::Error,
+                        name,
+                        format!("Executable {} doesn't exist", status.exe.display()),
+                    )
+                    .with_fix(upgrade),
+                ),
+                ExeState::Outdated => findings.push(
+                    Finding::for_shim(
+                        Severity::Warning,
+                        name,
+                        format!(
+                            "Executable {} differs from the current shim template",
+                            status.exe.display()
+                        ),
+                    )
+                    .with_fix(upgrade),
+                ),
+            }
+
+            if status.exe_state == ExeState::Disabled {
+                continue;
+            }
+
+            let shadowing = path_dirs
+                .iter()
+                .take_while(|dir| !search::is_same_dir(dir, &self.shims_dir))
+                .find_map(|dir| search::find_in_dir(dir, name));
+
+            if let Some(shadowing) = shadowing {
+                findings.push(Finding::for_shim(
+                    Severity::Warning,
+                    name,
+                    format!("Shadowed by {}, earlier in PATH", shadowing.display()),
+                ));
+            }
+        }
+
+        for exe in self.orphaned_exes() {
+            findings.push(
+                Finding::new(
+                    Severity::Warning,
+                    format!("Executable {} has no shim descriptor", exe.display()),
+                )
+                .with_fix(Fix::RemoveExe { path: exe }),
+            );
+        }
+    }
+
+    /// Executables in the shims dir without a descriptor.
+    pub fn orphaned_exes(&self) -> Vec<PathBuf> {
+        let entries = match fs::read_dir(&self.shims_dir) {
+            Ok(entries) => entries,
+            Err(_) => return Vec::new(),
+        };
+
+        let mut exes: Vec<PathBuf> = entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.extension().is_some_and(|ext| ext == "exe")
+                    && !path.with_extension("shim").exists()
+            })
+            .collect();
+
+        exes.sort();
+        exes
+    }
+}
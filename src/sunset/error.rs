@@ -0,0 +1,80 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+/// Errors returned by the sunset library operations.
+#[derive(Debug)]
+pub enum SunsetError {
+    /// An I/O operation over `path` failed.
+    Io { path: PathBuf, source: io::Error },
+    /// The shim descriptor for the given name doesn't exist.
+    ShimNotFound(String),
+    /// The target of a shim couldn't be found.
+    TargetNotFound(String),
+    /// A shim descriptor couldn't be parsed.
+    InvalidDescriptor { path: PathBuf, message: String },
+    /// The sunset configuration file couldn't be parsed.
+    InvalidConfig { path: PathBuf, message: String },
+    /// A shim manifest couldn't be parsed.
+    InvalidManifest { path: PathBuf, message: String },
+    /// A shim bundle couldn't be parsed.
+    InvalidBundle { path: PathBuf, message: String },
+    /// A project file with shim overrides couldn't be parsed.
+    InvalidProject { path: PathBuf, message: String },
+    /// A required setting couldn't be determined.
+    Setting(String),
+    /// A value given to an operation is not valid.
+    InvalidArgument(String),
+    /// Shims referencing each other in a loop, in reference order.
+    Cycle(Vec<String>),
+}
+
+impl SunsetError {
+    pub fn io(path: impl Into<PathBuf>, source: io::Error) -> SunsetError {
+        SunsetError::Io {
+            path: path.into(),
+            source,
+        }
+    }
+}
+
+impl fmt::Display for SunsetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SunsetError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            SunsetError::ShimNotFound(name) => write!(f, "Shim {:?} doesn't exists", name),
+            SunsetError::TargetNotFound(target) => {
+                write!(f, "Cannot find executable {}", target)
+            }
+            SunsetError::InvalidDescriptor { path, message } => {
+                write!(f, "Invalid shim descriptor {}: {}", path.display(), message)
+            }
+            SunsetError::InvalidConfig { path, message } => {
+                write!(f, "Invalid config file {}: {}", path.display(), message)
+            }
+            SunsetError::InvalidManifest { path, message } => {
+                write!(f, "Invalid manifest {}: {}", path.display(), message)
+            }
+            SunsetError::InvalidBundle { path, message } => {
+                write!(f, "Invalid bundle {}: {}", path.display(), message)
+            }
+            SunsetError::InvalidProject { path, message } => {
+                write!(f, "Invalid project file {}: {}", path.display(), message)
+            }
+            SunsetError::Setting(message) => write!(f, "{}", message),
+            SunsetError::InvalidArgument(message) => write!(f, "{}", message),
+            SunsetError::Cycle(names) => write!(f, "Cycle detected: {}", names.join(" -> ")),
+        }
+    }
+}
+
+impl std::error::Error for SunsetError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SunsetError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+pub type Result<T> = std::result::Result<T, SunsetError>;
@@ -0,0 +1,163 @@
+//! Interpreters for script targets like `foo.py` or `foo.ps1`, which can't be run by
+//! themselves.
+
+use pathsearch::find_executable_in_path;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+use crate::error::{Result, SunsetError};
+
+/// Interpreters by target extension, used when `[interpreters]` in `sunset.toml` has none.
+const DEFAULTS: [(&str, &[&str]); 10] = [
+    ("py", &["python"]),
+    ("pyw", &["pythonw"]),
+    (
+        "ps1",
+        &[
+            "powershell",
+            "-NoProfile",
+            "-ExecutionPolicy",
+            "Bypass",
+            "-File",
+        ],
+    ),
+    ("sh", &["sh"]),
+    ("bash", &["bash"]),
+    ("cmd", &["cmd", "/d", "/c"]),
+    ("bat", &["cmd", "/d", "/c"]),
+    ("js", &["node"]),
+    ("rb", &["ruby"]),
+    ("pl", &["perl"]),
+];
+
+/// Extensions of targets that run by themselves.
+const EXECUTABLE_EXTENSIONS: [&str; 2] = ["exe", "com"];
+
+/// Parses an interpreter command: a program, an array with a program and its arguments, or
+/// `false` for none, which is an empty command.
+pub fn parse_command(value: &toml::Value, key: &str) -> std::result::Result<Vec<String>, String> {
+    let invalid = || format!("{} must be a string, an array of strings or false", key);
+
+    match value {
+        toml::Value::String(program) => Ok(vec![program.clone()]),
+        toml::Value::Boolean(false) => Ok(Vec::new()),
+        toml::Value::Array(items) => items
+            .iter()
+            .map(|item| item.as_str().map(String::from).ok_or_else(invalid))
+            .collect(),
+        _ => Err(invalid()),
+    }
+}
+
+/// Built-in interpreter for a lowercase extension.
+pub fn default_for(extension: &str) -> Option<Vec<String>> {
+    DEFAULTS
+        .iter()
+        .find(|(ext, _)| *ext == extension)
+        .map(|(_, command)| command.iter().map(|it| String::from(*it)).collect())
+}
+
+/// Interpreter command for `target`, if it needs one.
+///
+/// `explicit`, the `interpreter` of the descriptor, comes first. Then the interpreter for the
+/// extension of the target, from `configured` (the `[interpreters]` of `sunset.toml`) or the
+/// built-in ones, and finally the shebang line of the target. An empty command runs the target
+/// by itself.
+pub fn select(
+    target: &Path,
+    explicit: Option<&[String]>,
+    configured: &BTreeMap<String, Vec<String>>,
+) -> Option<Vec<String>> {
+    if let Some(command) = explicit {
+        return Some(command.to_vec()).filter(|it| !it.is_empty());
+    }
+
+    let extension = target
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase());
+
+    if let Some(extension) = extension {
+        if EXECUTABLE_EXTENSIONS.contains(&extension.as_str()) {
+            return None;
+        }
+
+        let command = configured
+            .get(&extension)
+            .cloned()
+            .or_else(|| default_for(&extension));
+
+        if let Some(command) = command {
+            return Some(command).filter(|it| !it.is_empty());
+        }
+    }
+
+    shebang(target)
+}
+
+/// Interpreter command in the `#!` line of a script, without `/usr/bin/env`.
+///
+/// Where the interpreter path doesn't exist, like `/bin/sh` on Windows, only its file name is
+/// kept so it's looked up in the PATH.
+pub fn shebang(target: &Path) -> Option<Vec<String>> {
+    let mut line = String::new();
+    BufReader::new(File::open(target).ok()?.take(512))
+        .read_line(&mut line)
+        .ok()?;
+
+    let mut words = line.strip_prefix("#!")?.split_whitespace().peekable();
+
+    let mut program = words.next()?;
+
+    if Path::new(program)
+        .file_name()
+        .is_some_and(|name| name == "env")
+    {
+        if words.peek() == Some(&"-S") {
+            words.next();
+        }
+        program = words.next()?;
+    }
+
+    let program = if Path::new(program).is_file() {
+        String::from(program)
+    } else {
+        Path::new(program)
+            .file_name()?
+            .to_string_lossy()
+            .into_owned()
+    };
+
+    Some(
+        std::iter::once(program)
+            .chain(words.map(String::from))
+            .collect(),
+    )
+}
+
+/// Finds the program of an interpreter command: in the PATH when it's a bare name.
+pub fn resolve_program(program: &str) -> Result<PathBuf> {
+    let path = Path::new(program);
+
+    if path.parent().is_some_and(|parent| parent != Path::new("")) {
+        return Ok(path.to_path_buf());
+    }
+
+    find_executable_in_path(program).ok_or(SunsetError::TargetNotFound(String::from(program)))
+}
+
+/// Interpreters by extension from the `[interpreters]` table of `sunset.toml`.
+pub fn parse_interpreters(
+    table: &toml::value::Table,
+) -> std::result::Result<BTreeMap<String, Vec<String>>, String> {
+    table
+        .iter()
+        .map(|(extension, value)| {
+            let key = format!("interpreters.{}", extension);
+            let extension = extension.trim_start_matches('.').to_lowercase();
+
+            Ok((extension, parse_command(value, &key)?))
+        })
+        .collect()
+}
@@ -1,2 +1,21 @@
+pub mod bundle;
+pub mod config;
+pub mod descriptor;
+pub mod doctor;
+pub mod error;
+pub mod interpreter;
+pub mod manifest;
+pub mod pathlist;
+pub mod pattern;
+pub mod project;
+pub mod prune;
+pub mod quoting;
+pub mod response;
+pub mod search;
 pub mod shim;
+pub mod shimdir;
 pub mod shimmer;
+pub mod which;
+
+pub use error::SunsetError;
+pub use shimmer::Sunset;
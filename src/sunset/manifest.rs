@@ -0,0 +1,308 @@
+//! Declarative shim sets: a manifest lists the shims a machine should have, and
+//! `Sunset::sync_plan` computes what to change to match it.
+
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+use toml::value::Table;
+
+use crate::config::{get_bool, get_str};
+use crate::error::{Result, SunsetError};
+use crate::shim::{self, get_env, get_strings};
+use crate::shimmer::{self, ExeState, NewShim, Sunset};
+
+/// Shims declared by a manifest file.
+#[derive(Debug, Clone)]
+pub struct Manifest {
+    /// Identifies the shims managed by this manifest. Defaults to the file stem.
+    pub name: String,
+    /// Shims to have, with their target in `target` resolved like `Sunset::create` does,
+    /// unless expanded at run time with `env_expand_path`.
+    pub shims: Vec<NewShim>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SyncAction {
+    Create,
+    Update,
+    Remove,
+    /// The shim exists but isn't managed by the manifest, so it's left as is.
+    Conflict,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncReason {
+    /// The shim doesn't exist.
+    Missing,
+    /// The descriptor differs from the manifest.
+    Descriptor,
+    /// The executable is missing or differs from its template.
+    Template,
+    /// The shim is managed by the manifest but no longer listed in it.
+    NotInManifest,
+    /// The shim exists but was made by hand or by another manifest.
+    NotManaged,
+}
+
+/// A change needed to match a manifest.
+#[derive(Debug, Clone, Serialize)]
+pub struct SyncStep {
+    pub name: String,
+    pub action: SyncAction,
+    pub reasons: Vec<SyncReason>,
+    /// Descriptor to write, for creations and updates.
+    #[serde(skip)]
+    content: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SyncPlan {
+    pub manifest: String,
+    pub steps: Vec<SyncStep>,
+}
+
+impl SyncPlan {
+    /// Whether the shims already match the manifest.
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+}
+
+/// Reads a manifest file.
+pub fn read_manifest(path: &Path) -> Result<Manifest> {
+    let content = fs::read_to_string(path).map_err(|err| SunsetError::io(path, err))?;
+
+    let default_name = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    parse_manifest(&content, &default_name).map_err(|message| SunsetError::InvalidManifest {
+        path: path.to_path_buf(),
+        message,
+    })
+}
+
+/// Parses the content of a manifest file.
+pub fn parse_manifest(content: &str, default_name: &str) -> std::result::Result<Manifest, String> {
+    let value: Table = toml::from_str(content).map_err(|err| err.to_string())?;
+
+    let name = String::from(get_str(&value, "name")?.unwrap_or(default_name));
+
+    if name.is_empty() {
+        return Err(String::from("name must not be empty"));
+    }
+
+    let shims = match value.get("shims") {
+        None => Vec::new(),
+        Some(shims_raw) => shims_raw
+            .as_array()
+            .ok_or("shims must be an array")?
+            .iter()
+            .map(|it| match it.as_table() {
+                Some(table) => parse_shim(table),
+                None => Err(String::from("shims items must be tables")),
+            })
+            .collect::<std::result::Result<Vec<NewShim>, String>>()?,
+    };
+
+    let mut names: Vec<&str> = shims.iter().filter_map(|it| it.name.as_deref()).collect();
+    names.sort();
+
+    if let Some(pair) = names.windows(2).find(|pair| pair[0] == pair[1]) {
+        return Err(format!("shim {} is declared more than once", pair[0]));
+    }
+
+    Ok(Manifest { name, shims })
+}
+
+fn parse_shim(table: &Table) -> std::result::Result<NewShim, String> {
+    let name = get_str(table, "name")?.ok_or("shim without name")?;
+    let path = get_str(table, "path")?.ok_or(format!("shim {} doesn't have a path", name))?;
+
+    let env = get_env(table)?;
+
+    Ok(NewShim {
+        target: String::from(path),
+        name: Some(String::from(name)),
+        args: get_strings(table, "args")?,
+        win: get_bool(table, "win")?,
+        hidden: get_bool(table, "hidden")?,
+        wait: get_bool(table, "wait")?,
+        env_expand_path: get_bool(table, "env_expand_path")?,
+        env_expand_args: get_bool(table, "env_expand_args")?,
+        env,
+        cwd: get_str(table, "cwd")?.map(String::from),
+        group: get_str(table, "group")?.map(String::from),
+        tags: get_strings(table, "tags")?,
+    })
+}
+
+impl Sunset {
+    /// Compares the shims with a manifest.
+    ///
+    /// Shims are only updated or removed when their descriptor says they are managed by the
+    /// manifest; shims with invalid descriptors are never removed. Other existing shims listed
+    /// in the manifest are conflicts, unless `adopt` takes them over.
+    pub fn sync_plan(&self, manifest: &Manifest, adopt: bool) -> Result<SyncPlan> {
+        let mut steps = Vec::new();
+
+        for new_shim in &manifest.shims {
+            let name = new_shim.name.clone().unwrap_or_default();
+            let shimfile = self.shimfile(&name);
+
+            let target = self.manifest_target(new_shim)?;
+
+            let mut table = self.descriptor_table(&target, new_shim);
+            table.insert(
+                String::from("managed_by"),
+                toml::Value::from(manifest.name.as_str()),
+            );
+
+            let content =
+                toml::to_string(&table).map_err(|err| SunsetError::InvalidDescriptor {
+                    path: shimfile.clone(),
+                    message: err.to_string(),
+                })?;
+
+            if !shimfile.exists() {
+                steps.push(SyncStep {
+                    name,
+                    action: SyncAction::Create,
+                    reasons: vec![SyncReason::Missing],
+                    content: Some(content),
+                });
+                continue;
+            }
+
+            let desired =
+                shim::parse_config(&content).map_err(|message| SunsetError::InvalidDescriptor {
+                    path: shimfile.clone(),
+                    message,
+                })?;
+
+            let current = shim::read_config(&shimfile).ok();
+            let managed = current
+                .as_ref()
+                .is_some_and(|config| config.managed_by.as_ref() == Some(&manifest.name));
+
+            if !managed && !adopt {
+                steps.push(SyncStep {
+                    name,
+                    action: SyncAction::Conflict,
+                    reasons: vec![SyncReason::NotManaged],
+                    content: None,
+                });
+                continue;
+            }
+
+            let mut reasons = Vec::new();
+
+            if !managed {
+                reasons.push(SyncReason::NotManaged);
+            }
+
+            if current.as_ref() != Some(&desired) {
+                reasons.push(SyncReason::Descriptor);
+            }
+
+            let exe_state =
+                shimmer::get_exe_state(&self.shimmed_exe(&name), &self.shim_exe(desired.win));
+
+            if matches!(exe_state, ExeState::Outdated | ExeState::Missing) {
+                reasons.push(SyncReason::Template);
+            }
+
+            if !reasons.is_empty() {
+                steps.push(SyncStep {
+                    name,
+                    action: SyncAction::Update,
+                    reasons,
+                    content: Some(content),
+                });
+            }
+        }
+
+        if self.shims_dir.is_dir() {
+            for name in self.list()? {
+                let declared = manifest
+                    .shims
+                    .iter()
+                    .any(|it| it.name.as_deref() == Some(name.as_str()));
+
+                if declared {
+                    continue;
+                }
+
+                let managed = shim::read_config(&self.shimfile(&name))
+                    .is_ok_and(|config| config.managed_by.as_ref() == Some(&manifest.name));
+
+                if managed {
+                    steps.push(SyncStep {
+                        name,
+                        action: SyncAction::Remove,
+                        reasons: vec![SyncReason::NotInManifest],
+                        content: None,
+                    });
+                }
+            }
+        }
+
+        Ok(SyncPlan {
+            manifest: manifest.name.clone(),
+            steps,
+        })
+    }
+
+    /// Target of a shim in a manifest as written to its descriptor: resolved in the PATH and
+    /// made absolute, and existing, unless expanded at run time.
+    fn manifest_target(&self, new_shim: &NewShim) -> Result<String> {
+        let env_expand_path = new_shim
+            .env_expand_path
+            .unwrap_or(self.options.env_expand_path);
+
+        if env_expand_path {
+            return Ok(new_shim.target.clone());
+        }
+
+        let target = shimmer::resolve_target(&new_shim.target)?;
+
+        if !target.is_file() {
+            return Err(SunsetError::TargetNotFound(new_shim.target.clone()));
+        }
+
+        Ok(target.to_string_lossy().into_owned())
+    }
+
+    /// Applies a step of a sync plan. Applying it again has no further effect. Conflicts fail
+    /// without changing the shim.
+    pub fn sync_apply(&self, step: &SyncStep) -> Result<()> {
+        let content = match (step.action, &step.content) {
+            (SyncAction::Remove, _) => return self.remove(&step.name, false).map(|_| ()),
+            (SyncAction::Create | SyncAction::Update, Some(content)) => content,
+            _ => {
+                return Err(SunsetError::InvalidArgument(format!(
+                    "shim {} isn't managed by the manifest, sync with --adopt to replace it",
+                    step.name
+                )));
+            }
+        };
+
+        fs::create_dir_all(&self.shims_dir).map_err(|err| SunsetError::io(&self.shims_dir, err))?;
+
+        let shimfile = self.shimfile(&step.name);
+        shimmer::write_atomically(&shimfile, content)?;
+
+        let config = shim::read_config(&shimfile)?;
+        let template = self.shim_exe(config.win);
+        let exe = self.shimmed_exe(&step.name);
+
+        if shimmer::get_exe_state(&exe, &template) != ExeState::Current {
+            shimmer::shim_create(&template, &exe)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,166 @@
+use crate::shim::env_expand;
+
+/// Separator between the entries of a PATH-like variable.
+pub const SEPARATOR: char = ';';
+
+/// Where to place a new entry in a PATH-like variable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Position {
+    Prepend,
+    Append,
+}
+
+/// Normalizes a PATH entry so that entries pointing to the same directory compare equal.
+///
+/// Expands `%VAR%` references, strips surrounding quotes and trailing separators,
+/// unifies slashes and ignores case, like Windows does when resolving the PATH.
+pub fn normalize(entry: &str) -> String {
+    let expanded = env_expand(entry.trim());
+
+    expanded
+        .trim()
+        .trim_matches('"')
+        .replace('/', "\\")
+        .trim_end_matches('\\')
+        .to_lowercase()
+}
+
+pub fn is_same_entry(entry: &str, other: &str) -> bool {
+    normalize(entry) == normalize(other)
+}
+
+pub fn split(value: &str) -> Vec<&str> {
+    value
+        .split(SEPARATOR)
+        .filter(|entry| !entry.trim().is_empty())
+        .collect()
+}
+
+pub fn contains(value: &str, entry: &str) -> bool {
+    split(value).iter().any(|it| is_same_entry(it, entry))
+}
+
+/// Whether the first entry of `value` is equivalent to `entry`.
+pub fn is_first(value: &str, entry: &str) -> bool {
+    split(value)
+        .first()
+        .is_some_and(|first| is_same_entry(first, entry))
+}
+
+/// Returns `value` with `entry` added at `position`. Existing entries are kept verbatim.
+pub fn insert(value: &str, entry: &str, position: Position) -> String {
+    let mut entries = split(value);
+
+    match position {
+        Position::Prepend => entries.insert(0, entry),
+        Position::Append => entries.push(entry),
+    }
+
+    entries.join(&SEPARATOR.to_string())
+}
+
+/// Returns `value` without any entry equivalent to `entry`. Other entries are kept verbatim.
+pub fn remove(value: &str, entry: &str) -> String {
+    split(value)
+        .into_iter()
+        .filter(|it| !is_same_entry(it, entry))
+        .collect::<Vec<&str>>()
+        .join(&SEPARATOR.to_string())
+}
+
+/// Returns `value` with `entry` at `position` and no other equivalent entry, moving it if it's
+/// already there.
+pub fn place(value: &str, entry: &str, position: Position) -> String {
+    insert(&remove(value, entry), entry, position)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_entries() {
+        let cases = [
+            (r"C:\Tools", r"c:\tools"),
+            (r"C:\Tools\", r"c:\tools"),
+            ("C:/Tools/", r"c:\tools"),
+            (r#""C:\Program Files\Tools""#, r"c:\program files\tools"),
+            (r"  C:\Tools  ", r"c:\tools"),
+            (r"%SUNSET_TEST_UNSET_VAR%C:\Tools", r"c:\tools"),
+        ];
+
+        for (entry, expected) in cases {
+            assert_eq!(normalize(entry), expected, "normalizing {:?}", entry);
+        }
+    }
+
+    #[test]
+    fn splits_and_finds_entries() {
+        let value = r"C:\Windows;;C:\Tools\; ;D:\bin";
+
+        assert_eq!(split(value), [r"C:\Windows", r"C:\Tools\", r"D:\bin"]);
+        assert!(contains(value, r"c:\tools"));
+        assert!(contains(value, "D:/bin/"));
+        assert!(!contains(value, r"C:\Tool"));
+        assert!(is_first(value, r"c:\windows\"));
+        assert!(!is_first(value, r"D:\bin"));
+        assert!(!is_first("", r"D:\bin"));
+    }
+
+    #[test]
+    fn inserts_entries() {
+        let cases = [
+            ("", Position::Prepend, r"C:\shims"),
+            ("", Position::Append, r"C:\shims"),
+            (r"C:\a;C:\b", Position::Prepend, r"C:\shims;C:\a;C:\b"),
+            (r"C:\a;C:\b", Position::Append, r"C:\a;C:\b;C:\shims"),
+            (r"C:\a;;C:\b;", Position::Append, r"C:\a;C:\b;C:\shims"),
+        ];
+
+        for (value, position, expected) in cases {
+            assert_eq!(
+                insert(value, r"C:\shims", position),
+                expected,
+                "{:?}",
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn removes_every_equivalent_entry() {
+        let cases = [
+            (r"C:\a;C:\shims;C:\b", r"C:\a;C:\b"),
+            (r"C:\shims\;C:\a;c:/SHIMS", r"C:\a"),
+            (r#""C:\shims";C:\a"#, r"C:\a"),
+            (r"C:\a;C:\b", r"C:\a;C:\b"),
+            (r"C:\shims", ""),
+        ];
+
+        for (value, expected) in cases {
+            assert_eq!(remove(value, r"C:\shims"), expected, "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn places_entries_once() {
+        let cases = [
+            (
+                r"C:\a;C:\b;C:\shims",
+                Position::Prepend,
+                r"C:\shims;C:\a;C:\b",
+            ),
+            (
+                r"C:\shims;C:\a;c:\shims\",
+                Position::Prepend,
+                r"C:\shims;C:\a",
+            ),
+            (r"C:\shims;C:\a", Position::Append, r"C:\a;C:\shims"),
+            (r"C:\a", Position::Prepend, r"C:\shims;C:\a"),
+        ];
+
+        for (value, position, expected) in cases {
+            assert_eq!(place(value, r"C:\shims", position), expected, "{:?}", value);
+        }
+    }
+}
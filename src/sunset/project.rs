@@ -0,0 +1,248 @@
+//! Per-project overrides: a `.sunset.toml` file in a directory tree changes the target or
+//! env of shims run from inside it, once its directory is trusted with `sunset trust`.
+
+use path_absolutize::Absolutize;
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml::value::Table;
+
+use crate::config;
+use crate::error::{Result, SunsetError};
+use crate::shim::{self, ShimConfig, ShimConfigEnvActionItem};
+use crate::shimmer;
+
+/// File with the overrides of a project, looked up from the current directory upwards.
+pub const PROJECT_FILE: &str = ".sunset.toml";
+
+/// Overrides of a shim found in a project file.
+#[derive(Debug, Clone, Serialize)]
+pub struct ProjectOverride {
+    /// The project file declaring the overrides.
+    pub file: PathBuf,
+    /// Why the project file couldn't be read, in which case nothing is overridden.
+    pub error: Option<String>,
+    /// Target replacing the descriptor's, relative to the directory of `file` if not absolute.
+    pub path: Option<String>,
+    /// Env actions applied after the descriptor's.
+    pub env: Vec<ShimConfigEnvActionItem>,
+}
+
+impl ProjectOverride {
+    /// Directory of the project, the one to trust.
+    pub fn dir(&self) -> &Path {
+        self.file.parent().unwrap_or(Path::new(""))
+    }
+
+    /// Warning for a trusted project file that is ignored because it couldn't be read.
+    pub fn warning(&self) -> Option<String> {
+        self.error
+            .as_ref()
+            .map(|error| format!("Ignoring project overrides: {}", error))
+    }
+
+    /// Applies the overrides to the configuration of the shim, unless the file was invalid.
+    pub fn apply(&self, config: &mut ShimConfig) {
+        if self.error.is_some() {
+            return;
+        }
+
+        if let Some(path) = &self.path {
+            config.path = self.dir().join(path).to_string_lossy().into_owned();
+        }
+
+        config.env.extend(self.env.iter().cloned());
+    }
+}
+
+/// Finds the overrides of shim `name` in the nearest trusted project file listing it, walking
+/// up from `dir`.
+///
+/// Project files in untrusted directories are skipped without being read. A trusted file that
+/// can't be read or parsed is returned with its `error`, whether it lists the shim or not.
+pub fn find_override(dir: &Path, name: &str) -> Result<Option<ProjectOverride>> {
+    let trusted = trusted_dirs()?;
+
+    for file in dir.ancestors().map(|it| it.join(PROJECT_FILE)) {
+        if !file.is_file() {
+            continue;
+        }
+
+        let project_dir = absolute(file.parent().unwrap_or(dir))?;
+
+        if !trusted.iter().any(|it| same_dir(it, &project_dir)) {
+            continue;
+        }
+
+        let found = fs::read_to_string(&file)
+            .map_err(|err| SunsetError::io(&file, err))
+            .and_then(|content| {
+                parse_override(&content, name).map_err(|message| SunsetError::InvalidProject {
+                    path: file.clone(),
+                    message,
+                })
+            });
+
+        match found {
+            Ok(None) => continue,
+            Ok(Some((path, env))) => {
+                return Ok(Some(ProjectOverride {
+                    file,
+                    error: None,
+                    path,
+                    env,
+                }));
+            }
+            Err(err) => {
+                return Ok(Some(ProjectOverride {
+                    file,
+                    error: Some(err.to_string()),
+                    path: None,
+                    env: Vec::new(),
+                }));
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+type Override = (Option<String>, Vec<ShimConfigEnvActionItem>);
+
+/// The `path` and `env` of `[shims.<name>]` in the content of a project file, if listed.
+pub fn parse_override(content: &str, name: &str) -> std::result::Result<Option<Override>, String> {
+    let value: Table = toml::from_str(content).map_err(|err| err.to_string())?;
+
+    let shims = match value.get("shims") {
+        None => return Ok(None),
+        Some(shims_raw) => shims_raw.as_table().ok_or("shims must be a table")?,
+    };
+
+    let table = match shims.iter().find(|(key, _)| key.eq_ignore_ascii_case(name)) {
+        None => return Ok(None),
+        Some((key, table_raw)) => table_raw
+            .as_table()
+            .ok_or(format!("shims.{} must be a table", key))?,
+    };
+
+    let path = match table.get("path") {
+        None => None,
+        Some(path) => Some(String::from(path.as_str().ok_or("path must be a string")?)),
+    };
+
+    let env = shim::get_env(table)?;
+
+    Ok(Some((path, env)))
+}
+
+/// Location of the trust list, `trusted.toml` next to `sunset.toml`.
+pub fn get_trust_file() -> PathBuf {
+    config::get_config_file().with_file_name("trusted.toml")
+}
+
+/// Directories whose project files are used. A missing trust list trusts nothing.
+pub fn trusted_dirs() -> Result<Vec<PathBuf>> {
+    let path = get_trust_file();
+
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let content = fs::read_to_string(&path).map_err(|err| SunsetError::io(&path, err))?;
+
+    parse_trusted(&content).map_err(|message| SunsetError::InvalidConfig { path, message })
+}
+
+fn parse_trusted(content: &str) -> std::result::Result<Vec<PathBuf>, String> {
+    let value: Table = toml::from_str(content).map_err(|err| err.to_string())?;
+
+    match value.get("dirs") {
+        None => Ok(Vec::new()),
+        Some(dirs) => dirs
+            .as_array()
+            .ok_or("dirs must be an array")?
+            .iter()
+            .map(|dir| {
+                dir.as_str()
+                    .map(PathBuf::from)
+                    .ok_or(String::from("dirs must be strings"))
+            })
+            .collect(),
+    }
+}
+
+fn write_trusted(dirs: &[PathBuf]) -> Result<()> {
+    let path = get_trust_file();
+
+    let mut table = Table::new();
+    table.insert(
+        String::from("dirs"),
+        toml::Value::from(
+            dirs.iter()
+                .map(|dir| dir.to_string_lossy().into_owned())
+                .collect::<Vec<String>>(),
+        ),
+    );
+
+    let content = toml::to_string(&table).map_err(|err| SunsetError::Setting(err.to_string()))?;
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|err| SunsetError::io(parent, err))?;
+    }
+
+    shimmer::write_atomically(&path, &content)
+}
+
+fn same_dir(a: &Path, b: &Path) -> bool {
+    if cfg!(windows) {
+        a.to_string_lossy()
+            .eq_ignore_ascii_case(&b.to_string_lossy())
+    } else {
+        a == b
+    }
+}
+
+fn absolute(dir: &Path) -> Result<PathBuf> {
+    dir.absolutize()
+        .map(|it| it.into_owned())
+        .map_err(|err| SunsetError::io(dir, err))
+}
+
+/// Whether the project files in `dir` are used. Subdirectories must be trusted on their own.
+pub fn is_trusted(dir: &Path) -> Result<bool> {
+    let dir = absolute(dir)?;
+
+    Ok(trusted_dirs()?.iter().any(|it| same_dir(it, &dir)))
+}
+
+/// Adds `dir` to the trust list, returning its absolute path and whether it was added.
+pub fn trust(dir: &Path) -> Result<(PathBuf, bool)> {
+    let dir = absolute(dir)?;
+    let mut dirs = trusted_dirs()?;
+
+    if dirs.iter().any(|it| same_dir(it, &dir)) {
+        return Ok((dir, false));
+    }
+
+    dirs.push(dir.clone());
+    write_trusted(&dirs)?;
+
+    Ok((dir, true))
+}
+
+/// Removes `dir` from the trust list, returning its absolute path and whether it was there.
+pub fn untrust(dir: &Path) -> Result<(PathBuf, bool)> {
+    let dir = absolute(dir)?;
+    let mut dirs = trusted_dirs()?;
+    let before = dirs.len();
+
+    dirs.retain(|it| !same_dir(it, &dir));
+
+    if dirs.len() == before {
+        return Ok((dir, false));
+    }
+
+    write_trusted(&dirs)?;
+
+    Ok((dir, true))
+}
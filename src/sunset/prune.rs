@@ -0,0 +1,86 @@
+use serde::Serialize;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::error::{Result, SunsetError};
+use crate::shimmer::{ExeState, Sunset};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PruneReason {
+    /// An executable without descriptor.
+    OrphanedExe,
+    /// A descriptor without executable.
+    OrphanedDescriptor,
+    /// A shim whose target doesn't exist anymore.
+    MissingTarget,
+}
+
+/// A shim, or part of one, that can be pruned.
+#[derive(Debug, Clone, Serialize)]
+pub struct PruneItem {
+    pub name: String,
+    pub reason: PruneReason,
+    /// Files removed when pruning the item.
+    pub files: Vec<PathBuf>,
+}
+
+impl Sunset {
+    /// Finds orphaned executables, orphaned descriptors and shims with missing targets.
+    ///
+    /// Shims with invalid descriptors are left alone, as they may be waiting for a fix.
+    pub fn prune_candidates(&self) -> Result<Vec<PruneItem>> {
+        let mut items: Vec<PruneItem> = self
+            .orphaned_exes()
+            .into_iter()
+            .map(|exe| PruneItem {
+                name: exe
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned())
+                    .unwrap_or_default(),
+                reason: PruneReason::OrphanedExe,
+                files: vec![exe],
+            })
+            .collect();
+
+        for status in self.list_status()? {
+            if status.config.is_none() {
+                continue;
+            }
+
+            if !status.target_exists {
+                let mut files = vec![status.shimfile.clone()];
+                if !matches!(status.exe_state, ExeState::Missing | ExeState::Disabled) {
+                    files.push(status.exe.clone());
+                }
+
+                items.push(PruneItem {
+                    name: status.name,
+                    reason: PruneReason::MissingTarget,
+                    files,
+                });
+            } else if status.exe_state == ExeState::Missing {
+                items.push(PruneItem {
+                    name: status.name,
+                    reason: PruneReason::OrphanedDescriptor,
+                    files: vec![status.shimfile],
+                });
+            }
+        }
+
+        items.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(items)
+    }
+
+    /// Removes the files of a prune candidate.
+    pub fn prune(&self, item: &PruneItem) -> Result<()> {
+        for file in &item.files {
+            if file.exists() {
+                fs::remove_file(file).map_err(|err| SunsetError::io(file, err))?;
+            }
+        }
+
+        Ok(())
+    }
+}
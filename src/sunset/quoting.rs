@@ -0,0 +1,433 @@
+//! Quoting of arguments in Windows command lines, which programs split themselves.
+//!
+//! Most programs split their command line with the MSVCRT rules, but `cmd.exe`, and so `.cmd`
+//! and `.bat` targets, parses it with its own metacharacters first.
+
+use serde::Serialize;
+use std::process::Command;
+
+/// How the arguments of a shim are written in the command line of its target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Quoting {
+    /// Quotes and backslashes as the MSVCRT `argv` parser expects.
+    Msvcrt,
+    /// MSVCRT quoting with the `cmd.exe` metacharacters escaped with `^`.
+    Cmd,
+    /// The command line of the shim after its program name, passed verbatim.
+    Raw,
+}
+
+impl Quoting {
+    pub fn from_name(name: &str) -> Option<Quoting> {
+        match name {
+            "msvcrt" => Some(Quoting::Msvcrt),
+            "cmd" => Some(Quoting::Cmd),
+            "raw" => Some(Quoting::Raw),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Quoting::Msvcrt => "msvcrt",
+            Quoting::Cmd => "cmd",
+            Quoting::Raw => "raw",
+        }
+    }
+
+    /// Quoting for a target without `quoting` in its descriptor: `cmd` for `.cmd` and `.bat`
+    /// scripts, `msvcrt` otherwise.
+    pub fn for_target(target: &str) -> Quoting {
+        let extension = target
+            .rsplit_once('.')
+            .map(|(_, ext)| ext.to_lowercase())
+            .unwrap_or_default();
+
+        match extension.as_str() {
+            "cmd" | "bat" => Quoting::Cmd,
+            _ => Quoting::Msvcrt,
+        }
+    }
+}
+
+/// Characters `cmd.exe` interprets, even inside quotes.
+const CMD_METACHARACTERS: [char; 10] = ['(', ')', '%', '!', '^', '"', '<', '>', '&', '|'];
+
+/// Quotes `arg` so the MSVCRT parser reads it back as is. Arguments without spaces or quotes
+/// are left as they are.
+pub fn quote_msvcrt(arg: &str) -> String {
+    if !arg.is_empty() && !arg.contains([' ', '\t', '\n', '\x0b', '"']) {
+        return String::from(arg);
+    }
+
+    let mut quoted = String::from("\"");
+    let mut backslashes = 0;
+
+    for c in arg.chars() {
+        match c {
+            '\\' => backslashes += 1,
+            '"' => {
+                // The backslashes before a quote are doubled, and the quote is escaped.
+                quoted.extend(std::iter::repeat_n('\\', backslashes + 1));
+                backslashes = 0;
+            }
+            _ => backslashes = 0,
+        }
+        quoted.push(c);
+    }
+
+    // Backslashes before the closing quote are doubled too.
+    quoted.extend(std::iter::repeat_n('\\', backslashes));
+    quoted.push('"');
+
+    quoted
+}
+
+/// Quotes `arg` with `quote_msvcrt`, then escapes the `cmd.exe` metacharacters with `^`.
+pub fn quote_cmd(arg: &str) -> String {
+    let mut escaped = String::new();
+
+    for c in quote_msvcrt(arg).chars() {
+        if CMD_METACHARACTERS.contains(&c) {
+            escaped.push('^');
+        }
+        escaped.push(c);
+    }
+
+    escaped
+}
+
+/// Quotes `arg` for a command line with `quoting`. Raw command lines are only verbatim for the
+/// arguments of the caller; the others are quoted like `msvcrt`.
+pub fn quote(quoting: Quoting, arg: &str) -> String {
+    match quoting {
+        Quoting::Msvcrt | Quoting::Raw => quote_msvcrt(arg),
+        Quoting::Cmd => quote_cmd(arg),
+    }
+}
+
+/// Splits a command line, or the content of a response file, in arguments with the MSVCRT
+/// rules. Line breaks separate arguments like spaces do.
+pub fn split_msvcrt(line: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut in_arg = false;
+    let mut quoted = false;
+    let mut backslashes = 0;
+
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            backslashes += 1;
+            in_arg = true;
+            continue;
+        }
+
+        if c == '"' {
+            // 2n backslashes and a quote are n backslashes and a delimiter, 2n+1 backslashes
+            // and a quote are n backslashes and a literal quote.
+            // Inside quotes, two quotes are a literal quote that keeps the quotes open.
+            current.extend(std::iter::repeat_n('\\', backslashes / 2));
+            if backslashes % 2 == 1 {
+                current.push('"');
+            } else if quoted && chars.peek() == Some(&'"') {
+                chars.next();
+                current.push('"');
+            } else {
+                quoted = !quoted;
+            }
+            backslashes = 0;
+            in_arg = true;
+            continue;
+        }
+
+        current.extend(std::iter::repeat_n('\\', backslashes));
+        backslashes = 0;
+
+        if c.is_whitespace() && !quoted {
+            if in_arg {
+                args.push(std::mem::take(&mut current));
+                in_arg = false;
+            }
+        } else {
+            current.push(c);
+            in_arg = true;
+        }
+    }
+
+    current.extend(std::iter::repeat_n('\\', backslashes));
+
+    if in_arg {
+        args.push(current);
+    }
+
+    args
+}
+
+/// Joins `args` in a command line with the MSVCRT rules, which `split_msvcrt` splits back.
+pub fn join_msvcrt(args: &[String]) -> String {
+    args.iter()
+        .map(|arg| quote_msvcrt(arg))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// The arguments of a command line as written, after the program name.
+///
+/// The program name ends at the closing quote if it starts with one, otherwise at the first
+/// space or tab; backslashes don't escape anything in it.
+pub fn command_line_tail(line: &str) -> &str {
+    let rest = match line.strip_prefix('"') {
+        Some(quoted) => quoted.split_once('"').map_or("", |(_, rest)| rest),
+        None => line
+            .find([' ', '\t'])
+            .map_or("", |position| &line[position..]),
+    };
+
+    rest.trim_start_matches([' ', '\t'])
+}
+
+/// The arguments in the command line of the current process, as written. Only Windows has a
+/// command line; elsewhere programs get their arguments already split.
+#[cfg(windows)]
+pub fn raw_tail() -> Option<String> {
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn GetCommandLineW() -> *const u16;
+    }
+
+    // SAFETY: GetCommandLineW returns a NUL-terminated string that lives as long as the
+    // process.
+    let line = unsafe {
+        let ptr = GetCommandLineW();
+        if ptr.is_null() {
+            return None;
+        }
+        let len = (0..).take_while(|&i| *ptr.add(i) != 0).count();
+        String::from_utf16_lossy(std::slice::from_raw_parts(ptr, len))
+    };
+
+    Some(String::from(command_line_tail(&line)))
+}
+
+#[cfg(not(windows))]
+pub fn raw_tail() -> Option<String> {
+    None
+}
+
+/// Adds `args` to the command line of `cmd`, quoted with `quoting`. Elsewhere than in Windows
+/// they are passed as they are.
+pub fn push_args(cmd: &mut Command, quoting: Quoting, args: &[String]) {
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+
+        for arg in args {
+            cmd.raw_arg(quote(quoting, arg));
+        }
+    }
+
+    #[cfg(not(windows))]
+    {
+        let _ = quoting;
+        cmd.args(args);
+    }
+}
+
+/// Adds `tail`, from `raw_tail`, verbatim to the command line of `cmd`. Elsewhere than in
+/// Windows it's split with the MSVCRT rules.
+pub fn push_raw(cmd: &mut Command, tail: &str) {
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+
+        if !tail.is_empty() {
+            cmd.raw_arg(tail);
+        }
+    }
+
+    #[cfg(not(windows))]
+    {
+        cmd.args(split_msvcrt(tail));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quotes_msvcrt() {
+        let cases = [
+            ("", r#""""#),
+            ("plain", "plain"),
+            ("two words", r#""two words""#),
+            ("tab\there", "\"tab\there\""),
+            (r#"say "hi""#, r#""say \"hi\"""#),
+            (r"C:\dir\file", r"C:\dir\file"),
+            (r"C:\Program Files\", r#""C:\Program Files\\""#),
+            (r#"a\"b"#, r#""a\\\"b""#),
+            (r#"a\\"b"#, r#""a\\\\\"b""#),
+            (r"\\server\share", r"\\server\share"),
+            (r#"""#, r#""\"""#),
+            ("trailing\\\\", "trailing\\\\"),
+            ("ünïcödé words", "\"ünïcödé words\""),
+        ];
+
+        for (arg, expected) in cases {
+            assert_eq!(quote_msvcrt(arg), expected, "quoting {:?}", arg);
+        }
+    }
+
+    #[test]
+    fn quotes_cmd() {
+        let cases = [
+            ("plain", "plain"),
+            ("a&b", "a^&b"),
+            ("a|b", "a^|b"),
+            ("<in>out", "^<in^>out"),
+            ("(group)", "^(group^)"),
+            ("%PATH%", "^%PATH^%"),
+            ("bang!", "bang^!"),
+            ("caret^", "caret^^"),
+            ("two words", r#"^"two words^""#),
+            (r#"say "hi" & go"#, r#"^"say \^"hi\^" ^& go^""#),
+            ("", r#"^"^""#),
+        ];
+
+        for (arg, expected) in cases {
+            assert_eq!(quote_cmd(arg), expected, "quoting {:?}", arg);
+        }
+    }
+
+    #[test]
+    fn splits_msvcrt() {
+        let cases: [(&str, &[&str]); 15] = [
+            ("", &[]),
+            ("   ", &[]),
+            ("a b  c", &["a", "b", "c"]),
+            (r#""a b" c"#, &["a b", "c"]),
+            (r#""""#, &[""]),
+            (r#"a"b c"d"#, &["ab cd"]),
+            (r#"a\"b"#, &[r#"a"b"#]),
+            (r#"a\\"b c""#, &[r"a\b c"]),
+            (r#"a\\\"b"#, &[r#"a\"b"#]),
+            (r"C:\dir\ x", &[r"C:\dir\", "x"]),
+            (r#""a""b""#, &[r#"a"b"#]),
+            (r#""x""" y"#, &[r#"x""#, "y"]),
+            (r#"a""b"#, &["ab"]),
+            ("\"unterminated arg", &["unterminated arg"]),
+            ("line\r\nbreaks\n", &["line", "breaks"]),
+        ];
+
+        for (line, expected) in cases {
+            assert_eq!(split_msvcrt(line), expected, "splitting {:?}", line);
+        }
+    }
+
+    #[test]
+    fn splits_what_it_quotes() {
+        let args = [
+            "",
+            "plain",
+            "two words",
+            r#"say "hi""#,
+            r"C:\Program Files\",
+            r#"a\"b"#,
+            r#"a\\"b"#,
+            r"\\server\share\",
+            r#"""#,
+            r#"\""#,
+            "tab\tand\nnewline",
+            "%PATH% & (x) | y",
+        ];
+
+        for arg in args {
+            assert_eq!(
+                split_msvcrt(&quote_msvcrt(arg)),
+                [arg],
+                "round trip of {:?}",
+                arg
+            );
+        }
+
+        let line: Vec<String> = args.iter().map(|arg| quote_msvcrt(arg)).collect();
+        assert_eq!(split_msvcrt(&line.join(" ")), args);
+    }
+
+    #[test]
+    fn finds_command_line_tail() {
+        let cases = [
+            ("prog.exe", ""),
+            ("prog.exe a b", "a b"),
+            ("prog.exe \t  a  b ", "a  b "),
+            (r#""C:\Program Files\app.exe" "a b" c"#, r#""a b" c"#),
+            (r#""C:\Program Files\app.exe""#, ""),
+            (r#""C:\dir\app.exe"next"#, "next"),
+            (r#""C:\dir\"app.exe" x"#, r#"app.exe" x"#),
+            (r#"C:\dir\app.exe "%PATH%" ^&"#, r#""%PATH%" ^&"#),
+            ("\"unterminated", ""),
+        ];
+
+        for (line, expected) in cases {
+            assert_eq!(command_line_tail(line), expected, "tail of {:?}", line);
+        }
+    }
+
+    #[test]
+    fn rebuilds_raw_tail_of_forwarded_args() {
+        // `sunset run tool -- a "b c"` forwards the args after `--`, not its own command line.
+        let cases: [&[&str]; 5] = [
+            &[],
+            &["a", "b c"],
+            &[r#"say "hi""#, r"C:\Program Files\"],
+            &["", "%PATH% & (x)"],
+            &[r"\\server\share", "tab\there"],
+        ];
+
+        for args in cases {
+            let args: Vec<String> = args.iter().map(|arg| String::from(*arg)).collect();
+            let tail = join_msvcrt(&args);
+
+            assert_eq!(split_msvcrt(&tail), args, "tail {:?}", tail);
+            assert_eq!(
+                command_line_tail(&format!("tool.exe {}", tail)),
+                tail.trim_start(),
+                "tail {:?}",
+                tail
+            );
+
+            let mut cmd = Command::new("tool");
+            push_raw(&mut cmd, &tail);
+            if !cfg!(windows) {
+                let passed: Vec<String> = cmd
+                    .get_args()
+                    .map(|arg| arg.to_string_lossy().into_owned())
+                    .collect();
+                assert_eq!(passed, args, "tail {:?}", tail);
+            }
+        }
+    }
+
+    #[test]
+    fn selects_quoting() {
+        let cases = [
+            ("build.cmd", Quoting::Cmd),
+            (r"C:\tools\SETUP.BAT", Quoting::Cmd),
+            ("tool.exe", Quoting::Msvcrt),
+            ("script.py", Quoting::Msvcrt),
+            ("noext", Quoting::Msvcrt),
+        ];
+
+        for (target, expected) in cases {
+            assert_eq!(Quoting::for_target(target), expected, "target {:?}", target);
+        }
+
+        for quoting in [Quoting::Msvcrt, Quoting::Cmd, Quoting::Raw] {
+            assert_eq!(Quoting::from_name(quoting.name()), Some(quoting));
+        }
+        assert_eq!(Quoting::from_name("bash"), None);
+    }
+}
@@ -0,0 +1,133 @@
+//! Response files: arguments read from `@file` instead of the command line, which is limited
+//! to about 32K characters on Windows.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::error::{Result, SunsetError};
+use crate::quoting;
+
+/// Command line length above which `spill_args = true` writes the arguments to a response file.
+pub const DEFAULT_SPILL_LIMIT: usize = 30000;
+
+/// Age after which response files left by targets not waited for are removed.
+const STALE_SPILL_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// A response file written by `spill`, removed when dropped unless kept.
+#[derive(Debug)]
+pub struct SpillFile {
+    path: PathBuf,
+    keep: bool,
+}
+
+impl SpillFile {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Leaves the file for a target that isn't waited for. It's removed by a later `spill`
+    /// once stale.
+    pub fn keep(mut self) {
+        self.keep = true;
+    }
+}
+
+impl Drop for SpillFile {
+    fn drop(&mut self) {
+        if !self.keep {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// Replaces each `@file` argument naming an existing file with the arguments in the file.
+/// Other arguments, and `@file` arguments for missing files, are kept as they are.
+pub fn expand_args(args: &[String]) -> Result<Vec<String>> {
+    let mut expanded = Vec::with_capacity(args.len());
+
+    for arg in args {
+        match arg.strip_prefix('@').map(Path::new) {
+            Some(path) if path.is_file() => expanded.extend(read(path)?),
+            _ => expanded.push(arg.clone()),
+        }
+    }
+
+    Ok(expanded)
+}
+
+/// Reads the arguments of a response file, in UTF-8 or, with a BOM, UTF-16.
+pub fn read(path: &Path) -> Result<Vec<String>> {
+    let bytes = fs::read(path).map_err(|err| SunsetError::io(path, err))?;
+
+    let content = match bytes.as_slice() {
+        [0xFF, 0xFE, rest @ ..] => {
+            let units: Vec<u16> = rest
+                .chunks_exact(2)
+                .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+                .collect();
+            String::from_utf16_lossy(&units)
+        }
+        [0xEF, 0xBB, 0xBF, rest @ ..] => String::from_utf8_lossy(rest).into_owned(),
+        _ => String::from_utf8_lossy(&bytes).into_owned(),
+    };
+
+    Ok(quoting::split_msvcrt(&content))
+}
+
+/// Length of the command line for `program` and `args`, as passed to the OS.
+pub fn command_line_length(program: &str, args: &[String]) -> usize {
+    args.iter()
+        .map(|arg| quoting::quote_msvcrt(arg).len() + 1)
+        .sum::<usize>()
+        + quoting::quote_msvcrt(program).len()
+}
+
+/// Writes `args` to a new response file in the temp directory, one per line, quoted with the
+/// MSVCRT rules.
+pub fn spill(args: &[String]) -> Result<SpillFile> {
+    let dir = env::temp_dir();
+    remove_stale_spills(&dir);
+
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+
+    let path = dir.join(format!("sunset-{}-{}.rsp", process::id(), millis));
+
+    let content: Vec<String> = args.iter().map(|arg| quoting::quote_msvcrt(arg)).collect();
+
+    fs::write(&path, content.join("\n") + "\n").map_err(|err| SunsetError::io(&path, err))?;
+
+    Ok(SpillFile { path, keep: false })
+}
+
+/// Removes the response files in `dir` older than `STALE_SPILL_AGE`.
+fn remove_stale_spills(dir: &Path) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+
+        if !name.starts_with("sunset-") || !name.ends_with(".rsp") {
+            continue;
+        }
+
+        let stale = entry
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| modified.elapsed().ok())
+            .is_some_and(|age| age > STALE_SPILL_AGE);
+
+        if stale {
+            let _ = fs::remove_file(entry.path());
+        }
+    }
+}
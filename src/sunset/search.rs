@@ -0,0 +1,120 @@
+use std::env;
+use std::path::{Path, PathBuf};
+
+use crate::pathlist;
+
+/// Directories in the PATH of the current process, in order.
+pub fn path_dirs() -> Vec<PathBuf> {
+    match env::var_os("PATH") {
+        Some(path) => env::split_paths(&path)
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+/// Extensions tried when looking up a command without one.
+///
+/// On Windows these come from `PATHEXT`; elsewhere only the bare name is tried.
+pub fn executable_extensions() -> Vec<String> {
+    if cfg!(windows) {
+        let pathext = env::var("PATHEXT").unwrap_or(String::from(".COM;.EXE;.BAT;.CMD"));
+
+        pathext
+            .split(pathlist::SEPARATOR)
+            .filter(|ext| !ext.is_empty())
+            .map(|ext| ext.to_lowercase())
+            .collect()
+    } else {
+        vec![String::new()]
+    }
+}
+
+/// Whether two directories are the same PATH entry.
+pub fn is_same_dir(a: &Path, b: &Path) -> bool {
+    pathlist::is_same_entry(&a.to_string_lossy(), &b.to_string_lossy())
+}
+
+/// Finds the executable the OS would run for `command` in `dir`, if any.
+pub fn find_in_dir(dir: &Path, command: &str) -> Option<PathBuf> {
+    let has_extension = Path::new(command).extension().is_some();
+
+    if has_extension || !cfg!(windows) {
+        let candidate = dir.join(command);
+        if is_executable(&candidate) {
+            return Some(candidate);
+        }
+    }
+
+    if cfg!(windows) {
+        for ext in executable_extensions() {
+            let candidate = dir.join(format!("{}{}", command, ext));
+            if is_executable(&candidate) {
+                return Some(candidate);
+            }
+        }
+    }
+
+    None
+}
+
+/// Every executable for `command` in the PATH, in the order the OS would try them.
+pub fn find_all(command: &str) -> Vec<PathBuf> {
+    path_dirs()
+        .iter()
+        .filter_map(|dir| find_in_dir(dir, command))
+        .collect()
+}
+
+/// Finds the executable the OS would run for `command`.
+///
+/// A command with a directory is checked as is; a bare name is searched in the PATH.
+pub fn find(command: &str) -> Option<PathBuf> {
+    let command_path = Path::new(command);
+
+    match command_path.parent() {
+        Some(parent) if parent != Path::new("") => {
+            let file_name = command_path.file_name()?.to_str()?;
+            find_in_dir(parent, file_name)
+        }
+        _ => find_all(command).into_iter().next(),
+    }
+}
+
+/// Executables directly in `dir`, sorted: files with a `PATHEXT` extension on Windows,
+/// files with the exec bit elsewhere.
+pub fn executables_in_dir(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let extensions = executable_extensions();
+
+    let mut executables: Vec<PathBuf> = dir
+        .read_dir()?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            let has_extension = !cfg!(windows)
+                || path.extension().is_some_and(|ext| {
+                    let ext = format!(".{}", ext.to_string_lossy().to_lowercase());
+                    extensions.contains(&ext)
+                });
+
+            has_extension && is_executable(path)
+        })
+        .collect();
+
+    executables.sort();
+
+    Ok(executables)
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    path.metadata()
+        .is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
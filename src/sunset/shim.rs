@@ -1,30 +1,89 @@
 use once_cell::sync::Lazy;
 use regex::{Captures, Regex};
+use serde::Serialize;
+use std::collections::BTreeMap;
 use std::env;
+use std::ffi::OsStr;
+#[cfg(windows)]
 use std::os::windows::process::CommandExt;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process;
 use std::process::{Command, Stdio};
-use toml::Value::Boolean;
 use toml::value::Table;
 
-#[derive(Debug)]
-enum ShimConfigEnvAction {
-    SET,
-    CLEAR,
-    APPEND,
-    PREPREND,
+use crate::config;
+use crate::error::{Result, SunsetError};
+use crate::interpreter;
+use crate::project::{self, ProjectOverride};
+use crate::quoting::{self, Quoting};
+use crate::response;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ShimConfigEnvAction {
+    Set,
+    Clear,
+    Append,
+    Prepend,
 }
 
-#[derive(Debug)]
+impl ShimConfigEnvAction {
+    pub fn from_name(name: &str) -> Option<ShimConfigEnvAction> {
+        match name {
+            "append" => Some(ShimConfigEnvAction::Append),
+            "prepend" => Some(ShimConfigEnvAction::Prepend),
+            "clear" => Some(ShimConfigEnvAction::Clear),
+            "set" => Some(ShimConfigEnvAction::Set),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ShimConfigEnvAction::Set => "set",
+            ShimConfigEnvAction::Clear => "clear",
+            ShimConfigEnvAction::Append => "append",
+            ShimConfigEnvAction::Prepend => "prepend",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
 pub struct ShimConfigEnvActionItem {
-    var: String,
-    action: ShimConfigEnvAction,
-    value: String,
-    separator: String,
+    pub var: String,
+    pub action: ShimConfigEnvAction,
+    pub value: String,
+    pub separator: String,
+}
+
+/// Syntax of the environment variable references expanded in `path` and `args`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExpandSyntax {
+    /// `%VAR%`, with `%%` for a literal `%`.
+    Windows,
+    /// `$VAR` or `${VAR}`, with `$$` for a literal `$`.
+    Unix,
+}
+
+impl ExpandSyntax {
+    pub fn from_name(name: &str) -> Option<ExpandSyntax> {
+        match name {
+            "windows" => Some(ExpandSyntax::Windows),
+            "unix" => Some(ExpandSyntax::Unix),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ExpandSyntax::Windows => "windows",
+            ExpandSyntax::Unix => "unix",
+        }
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
 pub struct ShimConfig {
     pub path: String,
     pub args: Vec<String>,
@@ -34,115 +93,232 @@ pub struct ShimConfig {
     pub wait: bool,
     pub env_expand_path: bool,
     pub env_expand_args: bool,
+    pub env_expand_syntax: ExpandSyntax,
+    /// Working directory for the target; the caller's one if not set.
+    pub cwd: Option<String>,
+    /// Set by `sunset disable`: the shim has no executable until enabled again.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub disabled: bool,
+    /// Group the shim belongs to, e.g. the shims of a tool created by `sunset shim-dir`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    /// Free-form labels to find the shim by.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Name of the manifest that manages the shim, set by `sunset sync`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub managed_by: Option<String>,
+    /// Names of the variants in the descriptor, sorted.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub variants: Vec<String>,
+    /// Variant merged into `path`, `args` and `env`, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub active: Option<String>,
+    /// For aliases, the shim whose descriptor this configuration comes from.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target_shim: Option<String>,
+    /// Command running the target, overriding the one chosen by its extension or shebang.
+    /// Empty to run the target by itself.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub interpreter: Option<Vec<String>>,
+    /// Expand the `@file` arguments of the caller with the arguments in the file.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub response_files: bool,
+    /// Command line length above which the arguments are passed in a response file.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spill_args: Option<usize>,
+    /// How to quote the arguments of the target; chosen by its extension if not set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quoting: Option<Quoting>,
 }
 
-fn map_single_env_action_item(table: &Table) -> ShimConfigEnvActionItem {
-    let var = table.get("var").expect("VAR").as_str().unwrap();
+fn get_str<'a>(table: &'a Table, key: &str) -> std::result::Result<Option<&'a str>, String> {
+    match table.get(key) {
+        None => Ok(None),
+        Some(value) => value
+            .as_str()
+            .map(Some)
+            .ok_or(format!("{} must be a string", key)),
+    }
+}
 
-    let action_str = match table.get("action") {
-        None => "set",
-        Some(action) => action.as_str().unwrap(),
-    };
+fn get_bool(table: &Table, key: &str, default: bool) -> std::result::Result<bool, String> {
+    match table.get(key) {
+        None => Ok(default),
+        Some(value) => value.as_bool().ok_or(format!("{} must be a boolean", key)),
+    }
+}
 
-    let action = match action_str {
-        "append" => ShimConfigEnvAction::APPEND,
-        "prepend" => ShimConfigEnvAction::PREPREND,
-        "clear" => ShimConfigEnvAction::CLEAR,
-        "set" => ShimConfigEnvAction::SET,
-        _ => {
-            panic!("{} is not a valid action for variable {}", action_str, var)
-        }
-    };
+pub(crate) fn get_strings(table: &Table, key: &str) -> std::result::Result<Vec<String>, String> {
+    match table.get(key) {
+        None => Ok(Vec::new()),
+        Some(value) => value
+            .as_array()
+            .ok_or(format!("{} must be an array", key))?
+            .iter()
+            .map(|e| {
+                e.as_str()
+                    .map(String::from)
+                    .ok_or(format!("{} must be strings", key))
+            })
+            .collect(),
+    }
+}
 
-    let value = match table.get("value") {
-        None => "",
-        Some(value) => value.as_str().unwrap(),
-    };
+pub(crate) fn get_env(table: &Table) -> std::result::Result<Vec<ShimConfigEnvActionItem>, String> {
+    match table.get("env") {
+        None => Ok(Vec::new()),
+        Some(env_raw) => env_raw
+            .as_array()
+            .ok_or("env must be an array")?
+            .iter()
+            .map(|it| match it.as_table() {
+                Some(table) => map_single_env_action_item(table),
+                None => Err(String::from("env items must be tables")),
+            })
+            .collect(),
+    }
+}
 
-    let separator = match table.get("separator") {
-        None => "",
-        Some(separator) => separator.as_str().unwrap(),
-    };
+fn map_single_env_action_item(
+    table: &Table,
+) -> std::result::Result<ShimConfigEnvActionItem, String> {
+    let var = get_str(table, "var")?.ok_or("env item without var")?;
+
+    let action_str = get_str(table, "action")?.unwrap_or("set");
 
-    ShimConfigEnvActionItem {
+    let action = ShimConfigEnvAction::from_name(action_str).ok_or(format!(
+        "{} is not a valid action for variable {}",
+        action_str, var
+    ))?;
+
+    let value = get_str(table, "value")?.unwrap_or("");
+
+    let separator = get_str(table, "separator")?.unwrap_or("");
+
+    Ok(ShimConfigEnvActionItem {
         var: String::from(var),
         action,
         value: String::from(value),
         separator: String::from(separator),
-    }
+    })
 }
 
-pub fn read_config(path: &Path) -> std::io::Result<ShimConfig> {
-    let content = std::fs::read_to_string(path)?;
-    let value: Table = toml::from_str(&content).expect("Couldn't get a value");
+/// Parses the content of a shim descriptor, with its `active` variant if any.
+pub fn parse_config(content: &str) -> std::result::Result<ShimConfig, String> {
+    parse_config_variant(content, None)
+}
 
-    let path = String::from(
-        value
-            .get("path")
-            .expect("Doesn't have a path")
-            .as_str()
-            .unwrap(),
-    );
+/// Parses the content of a shim descriptor, merging `variant` instead of the `active` one.
+///
+/// The `path` of the variant replaces the descriptor's, and its `args` and `env` are
+/// added after the descriptor's. Descriptors without variants ignore `variant`.
+pub fn parse_config_variant(
+    content: &str,
+    variant: Option<&str>,
+) -> std::result::Result<ShimConfig, String> {
+    let value: Table = toml::from_str(content).map_err(|err| err.to_string())?;
+
+    config_from_table(&value, variant)
+}
 
-    let args_raw = value.get("args");
+fn config_from_table(
+    value: &Table,
+    variant: Option<&str>,
+) -> std::result::Result<ShimConfig, String> {
+    let mut path = get_str(value, "path")?.map(String::from);
+    let mut args = get_strings(value, "args")?;
+    let mut env = get_env(value)?;
+
+    let variants = match value.get("variants") {
+        None => Table::new(),
+        Some(variants_raw) => variants_raw
+            .as_table()
+            .ok_or("variants must be a table")?
+            .clone(),
+    };
 
-    let mut args: Vec<String> = match args_raw {
-        None => Vec::new(),
-        Some(args_raw) => args_raw
-            .as_array()
-            .unwrap()
-            .iter()
-            .map(|e| String::from(e.as_str().unwrap()))
-            .collect(),
+    // A selection from the environment doesn't apply to shims without variants.
+    let variant = variant.filter(|_| !variants.is_empty());
+
+    let active = match variant.or(get_str(value, "active")?) {
+        None => None,
+        Some(name) => {
+            let table = variants
+                .get(name)
+                .and_then(|it| it.as_table())
+                .ok_or(format!(
+                    "Variant {} doesn't exist, available: {}",
+                    name,
+                    variants.keys().cloned().collect::<Vec<_>>().join(", ")
+                ))?;
+
+            if let Some(variant_path) = get_str(table, "path")? {
+                path = Some(String::from(variant_path));
+            }
+            args.extend(get_strings(table, "args")?);
+            env.extend(get_env(table)?);
+
+            Some(String::from(name))
+        }
     };
 
-    let mut cmd_args: Vec<String> = env::args().skip(1).collect();
+    let path = path.ok_or(if variants.is_empty() {
+        "Doesn't have a path"
+    } else {
+        "Doesn't have a path, and no variant is active"
+    })?;
 
-    args.append(&mut cmd_args);
+    if path.trim().is_empty() {
+        return Err(String::from("path cannot be empty"));
+    }
 
-    let env_raw = value.get("env");
+    let win = get_bool(value, "win", false)?;
+    let hidden = get_bool(value, "hidden", false)?;
+    let wait = get_bool(value, "wait", true)?;
+    let env_expand_path = get_bool(value, "env_expand_path", false)?;
+    let env_expand_args = get_bool(value, "env_expand_args", false)?;
+    let disabled = get_bool(value, "disabled", false)?;
+
+    let env_expand_syntax = match get_str(value, "env_expand_syntax")? {
+        None => ExpandSyntax::Windows,
+        Some(syntax) => ExpandSyntax::from_name(syntax)
+            .ok_or(format!("{} is not a valid env_expand_syntax", syntax))?,
+    };
 
-    let env = match env_raw {
-        None => Vec::new(),
-        Some(env_raw) => env_raw
-            .as_array()
-            .unwrap()
-            .iter()
-            .map(|it| map_single_env_action_item(it.as_table().unwrap()))
-            .collect(),
+    let cwd = get_str(value, "cwd")?.map(String::from);
+    let group = get_str(value, "group")?.map(String::from);
+
+    let interpreter = match value.get("interpreter") {
+        None => None,
+        Some(interpreter) => Some(interpreter::parse_command(interpreter, "interpreter")?),
+    };
+
+    let response_files = get_bool(value, "response_files", false)?;
+
+    let spill_args = match value.get("spill_args") {
+        None | Some(toml::Value::Boolean(false)) => None,
+        Some(toml::Value::Boolean(true)) => Some(response::DEFAULT_SPILL_LIMIT),
+        Some(toml::Value::Integer(limit)) if *limit > 0 => Some(*limit as usize),
+        Some(_) => {
+            return Err(String::from(
+                "spill_args must be a boolean or a positive number of characters",
+            ));
+        }
+    };
+
+    let quoting = match get_str(value, "quoting")? {
+        None => None,
+        Some(name) => Some(Quoting::from_name(name).ok_or(format!(
+            "{} is not a valid quoting, use msvcrt, cmd or raw",
+            name
+        ))?),
     };
 
-    let win = value
-        .get("win")
-        .unwrap_or(&Boolean(false))
-        .as_bool()
-        .unwrap();
-
-    let hidden = value
-        .get("hidden")
-        .unwrap_or(&Boolean(false))
-        .as_bool()
-        .unwrap();
-
-    let wait = value
-        .get("wait")
-        .unwrap_or(&Boolean(true))
-        .as_bool()
-        .unwrap();
-
-    let env_expand_path = value
-        .get("env_expand_path")
-        .unwrap_or(&Boolean(false))
-        .as_bool()
-        .unwrap();
-
-    let env_expand_args = value
-        .get("env_expand_args")
-        .unwrap_or(&Boolean(false))
-        .as_bool()
-        .unwrap();
-
-    let ret_value = ShimConfig {
+    let tags = get_strings(value, "tags")?;
+    let managed_by = get_str(value, "managed_by")?.map(String::from);
+
+    Ok(ShimConfig {
         path,
         args,
         env,
@@ -151,114 +327,630 @@ pub fn read_config(path: &Path) -> std::io::Result<ShimConfig> {
         wait,
         env_expand_path,
         env_expand_args,
+        env_expand_syntax,
+        cwd,
+        disabled,
+        group,
+        tags,
+        managed_by,
+        variants: variants.keys().cloned().collect(),
+        active,
+        target_shim: None,
+        interpreter,
+        response_files,
+        spill_args,
+        quoting,
+    })
+}
+
+/// Keys an alias descriptor can have; everything else comes from the aliased shim.
+const ALIAS_KEYS: [&str; 2] = ["target_shim", "disabled"];
+
+/// The shim an alias descriptor refers to, and whether the alias is disabled.
+fn parse_alias(content: &str) -> std::result::Result<Option<(String, bool)>, String> {
+    let value: Table = toml::from_str(content).map_err(|err| err.to_string())?;
+
+    let target = match get_str(&value, "target_shim")? {
+        None => return Ok(None),
+        Some(target) => target,
+    };
+
+    if let Some(key) = value.keys().find(|key| !ALIAS_KEYS.contains(&key.as_str())) {
+        return Err(format!(
+            "{} is not allowed in an alias, edit shim {} instead",
+            key, target
+        ));
+    }
+
+    Ok(Some((
+        String::from(target),
+        get_bool(&value, "disabled", false)?,
+    )))
+}
+
+/// Name of the shim aliased by the descriptor `content`, if it's an alias.
+pub fn alias_of(content: &str) -> Option<String> {
+    parse_alias(content)
+        .ok()
+        .flatten()
+        .map(|(target, _)| target)
+}
+
+/// Directory with the descriptors that other descriptors extend, but that aren't shims.
+pub const TEMPLATES_DIR: &str = "templates";
+
+/// Keys of an extended descriptor that aren't inherited.
+const NOT_INHERITED: [&str; 6] = [
+    "extends",
+    "disabled",
+    "managed_by",
+    "target_shim",
+    "group",
+    "tags",
+];
+
+/// Descriptor extended by `name`: `templates/<name>.shim` if it exists, otherwise the
+/// descriptor of shim `name`.
+fn find_base(shims_dir: &Path, name: &str) -> Option<PathBuf> {
+    let file_name = format!("{}.shim", name);
+
+    [
+        shims_dir.join(TEMPLATES_DIR).join(&file_name),
+        shims_dir.join(&file_name),
+    ]
+    .into_iter()
+    .find(|path| path.is_file())
+}
+
+fn get_extends(table: &Table) -> std::result::Result<Vec<String>, String> {
+    match table.get("extends") {
+        None => Ok(Vec::new()),
+        Some(toml::Value::String(name)) => Ok(vec![name.clone()]),
+        Some(_) => get_strings(table, "extends")
+            .map_err(|_| String::from("extends must be a string or an array of strings")),
+    }
+}
+
+/// Names of the descriptors extended by the descriptor `content`, empty if it's invalid.
+pub fn extends_of(content: &str) -> Vec<String> {
+    toml::from_str::<Table>(content)
+        .ok()
+        .and_then(|table| get_extends(&table).ok())
+        .unwrap_or_default()
+}
+
+/// Whether `extends = name` in a descriptor refers to shim `name` rather than to a template.
+pub fn extends_shim(shims_dir: &Path, name: &str) -> bool {
+    !shims_dir
+        .join(TEMPLATES_DIR)
+        .join(format!("{}.shim", name))
+        .is_file()
+}
+
+/// Merges `table` over `base`: `args` and `env` are appended, `variants` are replaced one by
+/// one, and every other key is replaced.
+fn merge_table(base: &mut Table, table: Table) {
+    for (key, value) in table {
+        let rest = match (base.get_mut(&key), value) {
+            (Some(toml::Value::Array(current)), toml::Value::Array(more))
+                if key == "args" || key == "env" =>
+            {
+                current.extend(more);
+                None
+            }
+            (Some(toml::Value::Table(current)), toml::Value::Table(more)) if key == "variants" => {
+                current.extend(more);
+                None
+            }
+            (_, value) => Some(value),
+        };
+
+        if let Some(value) = rest {
+            base.insert(key, value);
+        }
+    }
+}
+
+/// The table of the descriptor `content` at `path`, with the descriptors it `extends` merged
+/// in order before it. `chain` holds the descriptors being merged, to detect cycles.
+fn merge_extends(
+    shims_dir: &Path,
+    path: &Path,
+    content: &str,
+    chain: &mut Vec<PathBuf>,
+) -> Result<Table> {
+    let invalid = |message: String| SunsetError::InvalidDescriptor {
+        path: path.to_path_buf(),
+        message,
     };
 
-    Ok(ret_value)
+    let mut table: Table = toml::from_str(content).map_err(|err| invalid(err.to_string()))?;
+    let extends = get_extends(&table).map_err(invalid)?;
+    table.remove("extends");
+
+    if extends.is_empty() {
+        return Ok(table);
+    }
+
+    chain.push(path.to_path_buf());
+
+    let mut merged = Table::new();
+
+    for name in &extends {
+        let base_path = find_base(shims_dir, name)
+            .ok_or_else(|| invalid(format!("extends {}, which doesn't exist", name)))?;
+
+        if chain.contains(&base_path) {
+            let mut names: Vec<String> = chain.iter().map(|it| descriptor_name(it)).collect();
+            names.push(descriptor_name(&base_path));
+            return Err(SunsetError::Cycle(names));
+        }
+
+        let base_content =
+            std::fs::read_to_string(&base_path).map_err(|err| SunsetError::io(&base_path, err))?;
+
+        let mut base = merge_extends(shims_dir, &base_path, &base_content, chain)?;
+
+        for key in NOT_INHERITED {
+            base.remove(key);
+        }
+
+        merge_table(&mut merged, base);
+    }
+
+    chain.pop();
+    merge_table(&mut merged, table);
+
+    Ok(merged)
+}
+
+/// Name of a descriptor in messages, `templates/<name>` for templates.
+fn descriptor_name(path: &Path) -> String {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let in_templates = path
+        .parent()
+        .and_then(|dir| dir.file_name())
+        .is_some_and(|dir| dir == TEMPLATES_DIR);
+
+    if in_templates {
+        format!("{}/{}", TEMPLATES_DIR, stem)
+    } else {
+        stem
+    }
+}
+
+/// The descriptor at `path` as the shim sees it: aliases resolved and the descriptors it
+/// extends merged in, as TOML.
+pub fn resolved_descriptor(path: &Path) -> Result<String> {
+    let content = std::fs::read_to_string(path).map_err(|err| SunsetError::io(path, err))?;
+    let (current, content, _) = follow_alias(path, &content)?;
+    let shims_dir = path.parent().unwrap_or(Path::new(""));
+    let value = merge_extends(shims_dir, &current, &content, &mut Vec::new())?;
+
+    toml::to_string(&value).map_err(|err| SunsetError::InvalidDescriptor {
+        path: current,
+        message: err.to_string(),
+    })
+}
+
+/// Parses `content`, the descriptor at `path`, merging `variant` like `parse_config_variant`.
+///
+/// An alias, a descriptor with `target_shim`, takes the configuration of the shim it names
+/// in the same directory; only `disabled` is its own. Descriptors listed in `extends` are
+/// merged before the descriptor, see `merge_table`.
+pub fn parse_config_at(path: &Path, content: &str, variant: Option<&str>) -> Result<ShimConfig> {
+    let (current, content, alias) = follow_alias(path, content)?;
+
+    let shims_dir = path.parent().unwrap_or(Path::new(""));
+    let value = merge_extends(shims_dir, &current, &content, &mut Vec::new())?;
+
+    let mut config =
+        config_from_table(&value, variant).map_err(|message| SunsetError::InvalidDescriptor {
+            path: current.clone(),
+            message,
+        })?;
+
+    if let Some((target, disabled)) = alias {
+        config.target_shim = Some(target);
+        config.disabled = disabled;
+    }
+
+    Ok(config)
+}
+
+type Alias = (String, bool);
+
+/// Follows `target_shim` from the descriptor `content` at `path` to a descriptor that isn't an
+/// alias. Returns its path and content, with the target and `disabled` of the first alias.
+fn follow_alias(path: &Path, content: &str) -> Result<(PathBuf, String, Option<Alias>)> {
+    let invalid = |path: &Path, message: String| SunsetError::InvalidDescriptor {
+        path: path.to_path_buf(),
+        message,
+    };
+
+    let mut current = path.to_path_buf();
+    let mut content = String::from(content);
+    let mut visited = vec![descriptor_name(path)];
+    let mut alias = None;
+
+    while let Some((target, disabled)) = parse_alias(&content).map_err(|m| invalid(&current, m))? {
+        if visited.contains(&target) {
+            visited.push(target);
+            return Err(SunsetError::Cycle(visited));
+        }
+
+        let target_path = current.with_file_name(format!("{}.shim", target));
+
+        if !target_path.exists() {
+            return Err(invalid(
+                &current,
+                format!("target_shim {} doesn't exist", target),
+            ));
+        }
+
+        content = std::fs::read_to_string(&target_path)
+            .map_err(|err| SunsetError::io(&target_path, err))?;
+        current = target_path;
+        visited.push(target.clone());
+        alias.get_or_insert((target, disabled));
+    }
+
+    Ok((current, content, alias))
+}
+
+/// Reads and parses the shim descriptor at `path`.
+pub fn read_config(path: &Path) -> Result<ShimConfig> {
+    let content = std::fs::read_to_string(path).map_err(|err| SunsetError::io(path, err))?;
+
+    parse_config_at(path, &content, None)
+}
+
+/// File selecting variants for a directory tree, with `<shim> <variant>` lines.
+pub const VERSION_FILE: &str = ".sunset-version";
+
+/// Reads the descriptor of shim `name` at `path` with the variant selected for the invocation.
+///
+/// The variant comes from `SUNSET_USE_<NAME>`, then from the nearest `.sunset-version` file
+/// listing the shim, walking up from the current directory, then from `active`. Shims without
+/// variants ignore the first two.
+pub fn read_config_for(path: &Path, name: &str) -> Result<ShimConfig> {
+    let content = std::fs::read_to_string(path).map_err(|err| SunsetError::io(path, err))?;
+
+    parse_config_at(path, &content, selected_variant(name).as_deref())
+}
+
+/// Name of the variable overriding the variant of shim `name`, e.g. `SUNSET_USE_NODE`.
+pub fn variant_var(name: &str) -> String {
+    let suffix: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    format!("SUNSET_USE_{}", suffix)
+}
+
+fn selected_variant(name: &str) -> Option<String> {
+    let from_env = env::var(variant_var(name))
+        .ok()
+        .filter(|variant| !variant.is_empty());
+
+    if from_env.is_some() {
+        return from_env;
+    }
+
+    let cwd = env::current_dir().ok()?;
+
+    cwd.ancestors()
+        .map(|dir| dir.join(VERSION_FILE))
+        .filter(|file| file.is_file())
+        .find_map(|file| {
+            let content = std::fs::read_to_string(file).ok()?;
+            version_file_variant(&content, name)
+        })
+}
+
+/// Variant for shim `name` in the content of a `.sunset-version` file.
+pub fn version_file_variant(content: &str, name: &str) -> Option<String> {
+    content
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default())
+        .filter_map(|line| {
+            let mut words = line.split_whitespace();
+            Some((words.next()?, words.next()?))
+        })
+        .find(|(shim, _)| shim.eq_ignore_ascii_case(name))
+        .map(|(_, variant)| String::from(variant))
 }
 
 static ENV_VAR: Lazy<Regex> = Lazy::new(|| Regex::new("%([[:word:]]*)%").expect("Invalid Regex"));
 
-pub fn env_expand(input: &String) -> String {
+static UNIX_ENV_VAR: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\$(?:\{([[:word:]]+)\}|([[:word:]]+)|(\$))").expect("Invalid Regex"));
+
+pub fn env_expand(input: &str) -> String {
     // Shamelessly ripped of from:
     // https://users.rust-lang.org/t/expand-win-env-var-in-string/50320/3
     ENV_VAR
-        .replace_all(input.as_str(), |c: &Captures| match &c[1] {
+        .replace_all(input, |c: &Captures| match &c[1] {
             "" => String::from("%"),
             varname => env::var(varname).unwrap_or("".to_string()),
         })
         .into()
 }
 
-pub fn main() {
-    // Catch Signals. If signals, set global semaphore.
+pub fn env_expand_with(input: &str, syntax: ExpandSyntax) -> String {
+    match syntax {
+        ExpandSyntax::Windows => env_expand(input),
+        ExpandSyntax::Unix => UNIX_ENV_VAR
+            .replace_all(input, |c: &Captures| match c.get(1).or(c.get(2)) {
+                None => String::from("$"),
+                Some(varname) => env::var(varname.as_str()).unwrap_or("".to_string()),
+            })
+            .into(),
+    }
+}
 
-    let exe_path = env::current_exe().expect("No arg 0? Crazy");
-    let shim_path_buf = exe_path.with_extension("shim");
+/// Value of `var` for the command, after the actions already applied to it.
+fn current_env_value(cmd: &Command, var: &str) -> String {
+    let applied = cmd
+        .get_envs()
+        .filter(|(name, _)| *name == OsStr::new(var))
+        .last();
+
+    match applied {
+        Some((_, Some(value))) => value.to_string_lossy().into_owned(),
+        Some((_, None)) => String::new(),
+        None => env::var(var).unwrap_or_default(),
+    }
+}
 
-    // println!("Reading exe file at: {:?}", &exe_path);
-    // println!("Reading shim file at: {:?}", &shim_path);
-    // dbg!(env::vars());
+/// Runs the target of `config` with `caller_args` after the descriptor args, applying response
+/// files, quoting, the env actions, expansion, hidden and wait settings. Returns the exit code of the
+/// target, or 0 when not waiting for it.
+///
+/// `raw_tail` is the caller's command line as written, passed instead of `caller_args` with
+/// `raw` quoting. Without one, `caller_args` are quoted like `msvcrt`. `interpreters` are the
+/// ones configured in `sunset.toml` for script targets.
+pub fn launch(
+    config: &ShimConfig,
+    caller_args: &[String],
+    raw_tail: Option<&str>,
+    interpreters: &BTreeMap<String, Vec<String>>,
+) -> Result<i32> {
+    let path: String = if config.env_expand_path {
+        env_expand_with(&config.path, config.env_expand_syntax)
+    } else {
+        config.path.clone()
+    };
 
-    let shim_path = shim_path_buf.as_path();
+    let args: Vec<String> = if config.env_expand_args {
+        config
+            .args
+            .iter()
+            .map(|arg| env_expand_with(arg, config.env_expand_syntax))
+            .collect()
+    } else {
+        config.args.clone()
+    };
 
-    let config = read_config(shim_path)
-        .expect(format!("Error reading file: {}", shim_path.display()).as_str());
+    let quoting = config.quoting.unwrap_or_else(|| Quoting::for_target(&path));
 
-    // dbg!(&config);
+    // A raw command line replaces the caller args, it's passed as written.
+    let raw_tail = match quoting {
+        Quoting::Raw => raw_tail,
+        _ => None,
+    };
 
-    let path: String = if config.env_expand_path {
-        env_expand(&config.path)
+    let caller_args = if raw_tail.is_some() {
+        Vec::new()
+    } else if config.response_files {
+        response::expand_args(caller_args)?
     } else {
-        config.path
+        caller_args.to_vec()
     };
 
-    let args = if config.env_expand_args {
-        config.args.iter().map(env_expand).collect()
-    } else {
-        config.args
+    let mut args: Vec<String> = args.into_iter().chain(caller_args).collect();
+
+    // An interpreter runs the target as its argument, after its own arguments.
+    let (program, interpreter_args) = match interpreter::select(
+        Path::new(&path),
+        config.interpreter.as_deref(),
+        interpreters,
+    ) {
+        None => (PathBuf::from(&path), Vec::new()),
+        Some(command) => {
+            let program = interpreter::resolve_program(&command[0])?;
+            let mut interpreter_args = command[1..].to_vec();
+            interpreter_args.push(path.clone());
+            (program, interpreter_args)
+        }
     };
 
-    let mut cmd = Command::new(path.to_string());
-    cmd.args(args);
+    // Removed when dropped, so also when failing to spawn the target.
+    let spilled = match config.spill_args {
+        Some(limit) => {
+            let line: Vec<String> = interpreter_args.iter().chain(&args).cloned().collect();
+            let length = response::command_line_length(&program.to_string_lossy(), &line);
+
+            if length > limit {
+                let file = response::spill(&args)?;
+                args = vec![format!("@{}", file.path().display())];
+                Some(file)
+            } else {
+                None
+            }
+        }
+        None => None,
+    };
+
+    let mut cmd = Command::new(&program);
+
+    if let Some((target, interpreter_args)) = interpreter_args.split_last() {
+        cmd.args(interpreter_args);
+        quoting::push_args(&mut cmd, quoting, std::slice::from_ref(target));
+    }
+
+    quoting::push_args(&mut cmd, quoting, &args);
+
+    if let Some(tail) = raw_tail {
+        quoting::push_raw(&mut cmd, tail);
+    }
 
-    for k in config.env {
+    if let Some(cwd) = &config.cwd {
+        if config.env_expand_path {
+            cmd.current_dir(env_expand_with(cwd, config.env_expand_syntax));
+        } else {
+            cmd.current_dir(cwd);
+        }
+    }
+
+    for k in &config.env {
         match k.action {
-            ShimConfigEnvAction::SET => {
-                cmd.env(k.var, k.value);
+            ShimConfigEnvAction::Set => {
+                cmd.env(&k.var, &k.value);
             }
-            ShimConfigEnvAction::CLEAR => {
-                cmd.env_remove(k.var);
+            ShimConfigEnvAction::Clear => {
+                cmd.env_remove(&k.var);
             }
-            ShimConfigEnvAction::APPEND => {
-                let current_var_value = match &env::var(&k.var) {
-                    Ok(value) => String::from(value),
-                    Err(_) => String::from(""),
-                };
+            ShimConfigEnvAction::Append => {
+                let current_var_value = current_env_value(&cmd, &k.var);
                 let mut value = String::from("");
                 value.push_str(&current_var_value);
                 value.push_str(&k.separator);
                 value.push_str(&k.value);
-                cmd.env(k.var, value);
+                cmd.env(&k.var, value);
             }
-            ShimConfigEnvAction::PREPREND => {
-                let current_var_value = match &env::var(&k.var) {
-                    Ok(value) => String::from(value),
-                    Err(_) => String::from(""),
-                };
+            ShimConfigEnvAction::Prepend => {
+                let current_var_value = current_env_value(&cmd, &k.var);
                 let mut value = String::from("");
                 value.push_str(&k.value);
                 value.push_str(&k.separator);
                 value.push_str(&current_var_value);
-                cmd.env(k.var, value);
+                cmd.env(&k.var, value);
             }
         }
     }
 
-    const CREATE_NO_WINDOW: u32 = 0x08000000;
-
     if config.hidden {
-        cmd.creation_flags(CREATE_NO_WINDOW)
-            .stdin(Stdio::null())
+        #[cfg(windows)]
+        {
+            const CREATE_NO_WINDOW: u32 = 0x08000000;
+            cmd.creation_flags(CREATE_NO_WINDOW);
+        }
+
+        cmd.stdin(Stdio::null())
             .stdout(Stdio::null())
             .stderr(Stdio::null());
     }
 
-    let mut child = cmd
-        .spawn()
-        .expect(format!("sunset: Failed to execute command {}", path).as_str());
+    let mut child = cmd.spawn().map_err(|err| SunsetError::io(&path, err))?;
 
     if !config.wait {
-        process::exit(0);
+        // The target may read the response file after the shim exits.
+        if let Some(file) = spilled {
+            file.keep();
+        }
+        return Ok(0);
     }
 
-    let exit_code = match child.try_wait() {
-        Ok(Some(status)) => status.code().unwrap(),
-        Ok(None) => {
-            let res = child.wait();
-            res.unwrap().code().unwrap()
-        }
+    let exit_code = match child.wait() {
+        Ok(status) => status.code().unwrap_or(-1),
         Err(_e) => -1,
     };
 
-    process::exit(exit_code);
+    Ok(exit_code)
+}
+
+/// Applies the overrides of the nearest trusted project file listing shim `name`, walking up
+/// from the current directory, and returns them. An invalid project file is returned without
+/// being applied, so the caller can warn about it.
+pub fn apply_project_override(
+    config: &mut ShimConfig,
+    name: &str,
+) -> Result<Option<ProjectOverride>> {
+    let cwd = env::current_dir().map_err(|err| SunsetError::io(".", err))?;
+
+    let found = project::find_override(&cwd, name)?;
+
+    if let Some(project_override) = &found {
+        project_override.apply(config);
+    }
+
+    Ok(found)
+}
+
+pub fn main() {
+    // Catch Signals. If signals, set global semaphore.
+
+    let exe_path = env::current_exe().expect("No arg 0? Crazy");
+    let shim_path_buf = exe_path.with_extension("shim");
+
+    // println!("Reading exe file at: {:?}", &exe_path);
+    // println!("Reading shim file at: {:?}", &shim_path);
+    // dbg!(env::vars());
+
+    let shim_path = shim_path_buf.as_path();
+
+    let name = exe_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let mut config = match read_config_for(shim_path, &name) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("sunset: Error reading file: {}", err);
+            process::exit(-1);
+        }
+    };
+
+    // Project overrides are optional, the descriptor is used as is when they can't be read.
+    match apply_project_override(&mut config, &name) {
+        Ok(project_override) => {
+            if let Some(warning) = project_override.and_then(|it| it.warning()) {
+                eprintln!("sunset: {}", warning);
+            }
+        }
+        Err(err) => eprintln!("sunset: Ignoring project overrides: {}", err),
+    }
+
+    // dbg!(&config);
+
+    let cmd_args: Vec<String> = env::args().skip(1).collect();
+
+    // Only scripts without an explicit interpreter need the ones of sunset.toml, and the
+    // built-in ones are enough when it can't be read.
+    let interpreters = match config.interpreter {
+        Some(_) => BTreeMap::new(),
+        None => match config::load() {
+            Ok(sunset_config) => sunset_config.interpreters,
+            Err(err) => {
+                eprintln!("sunset: Using the built-in interpreters: {}", err);
+                BTreeMap::new()
+            }
+        },
+    };
+
+    match launch(
+        &config,
+        &cmd_args,
+        quoting::raw_tail().as_deref(),
+        &interpreters,
+    ) {
+        Ok(exit_code) => process::exit(exit_code),
+        Err(err) => {
+            eprintln!("sunset: Failed to execute command {}", err);
+            process::exit(-1);
+        }
+    }
 }
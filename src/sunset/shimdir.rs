@@ -0,0 +1,140 @@
+//! Shims for every executable in a directory, like the `bin` folder of a JDK.
+
+use path_absolutize::Absolutize;
+use std::path::{Path, PathBuf};
+
+use crate::error::{Result, SunsetError};
+use crate::pattern;
+use crate::search;
+use crate::shimmer::{self, NewShim, Sunset};
+
+/// Which executables of a directory to shim, and how.
+#[derive(Debug, Default, Clone)]
+pub struct DirShims {
+    pub dir: PathBuf,
+    /// Globs the file name must match, any of them. Everything matches if empty.
+    pub include: Vec<String>,
+    /// Globs excluding a file name, even if included.
+    pub exclude: Vec<String>,
+    pub prefix: String,
+    pub suffix: String,
+    /// Group recorded in every shim; see `default_group`.
+    pub group: Option<String>,
+    /// Flags, args and env applied to every shim. Its target and name are ignored.
+    pub shared: NewShim,
+}
+
+/// Group for the shims of `dir`: its name, or its parent's when it's a `bin` directory.
+pub fn default_group(dir: &Path) -> Option<String> {
+    let name = |path: &Path| path.file_name().map(|it| it.to_string_lossy().into_owned());
+
+    match name(dir) {
+        Some(dir_name) if dir_name.eq_ignore_ascii_case("bin") => dir.parent().and_then(name),
+        other => other,
+    }
+}
+
+fn matches_any(globs: &[String], path: &Path) -> bool {
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+
+    globs
+        .iter()
+        .any(|glob| pattern::matches(glob, &file_name) || pattern::matches(glob, &stem))
+}
+
+impl Sunset {
+    /// The new shims for the executables of a directory, one per executable, sorted by target.
+    ///
+    /// Fails when executables like `java.exe` and `java.cmd` would get the same shim name, so
+    /// one doesn't silently replace the other.
+    pub fn dir_shims(&self, spec: &DirShims) -> Result<Vec<NewShim>> {
+        let dir = spec
+            .dir
+            .absolutize()
+            .map_err(|err| SunsetError::io(&spec.dir, err))?
+            .into_owned();
+
+        let executables =
+            search::executables_in_dir(&dir).map_err(|err| SunsetError::io(&dir, err))?;
+
+        let group = spec.group.clone().or_else(|| default_group(&dir));
+
+        let new_shims = executables
+            .iter()
+            .filter(|exe| spec.include.is_empty() || matches_any(&spec.include, exe))
+            .filter(|exe| !matches_any(&spec.exclude, exe))
+            .map(|exe| {
+                let name = format!(
+                    "{}{}{}",
+                    spec.prefix,
+                    shimmer::get_default_name(exe)?,
+                    spec.suffix
+                );
+
+                Ok(NewShim {
+                    target: exe.to_string_lossy().into_owned(),
+                    name: Some(name),
+                    group: group.clone(),
+                    ..spec.shared.clone()
+                })
+            })
+            .collect::<Result<Vec<NewShim>>>()?;
+
+        let conflicts = name_conflicts(&new_shims);
+
+        if !conflicts.is_empty() {
+            return Err(SunsetError::InvalidArgument(format!(
+                "Executables with the same shim name, exclude all but one of each: {}",
+                conflicts.join("; ")
+            )));
+        }
+
+        Ok(new_shims)
+    }
+}
+
+fn same_name(a: &str, b: &str) -> bool {
+    if cfg!(windows) {
+        a.eq_ignore_ascii_case(b)
+    } else {
+        a == b
+    }
+}
+
+/// Descriptions of the shim names given to more than one target, like `java: java.exe, java.sh`.
+fn name_conflicts(new_shims: &[NewShim]) -> Vec<String> {
+    let file_name = |new_shim: &NewShim| {
+        Path::new(&new_shim.target)
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned()
+    };
+
+    let mut conflicts = Vec::new();
+
+    for (index, new_shim) in new_shims.iter().enumerate() {
+        let name = new_shim.name.as_deref().unwrap_or_default();
+
+        let earlier = new_shims[..index]
+            .iter()
+            .any(|other| same_name(other.name.as_deref().unwrap_or_default(), name));
+
+        if earlier {
+            continue;
+        }
+
+        let targets: Vec<String> = new_shims[index..]
+            .iter()
+            .filter(|other| same_name(other.name.as_deref().unwrap_or_default(), name))
+            .map(file_name)
+            .collect();
+
+        if targets.len() > 1 {
+            conflicts.push(format!("{}: {}", name, targets.join(", ")));
+        }
+    }
+
+    conflicts
+}
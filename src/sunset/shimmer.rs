@@ -1,243 +1,904 @@
-use path_absolutize;
 use path_absolutize::Absolutize;
-use std::path::PathBuf;
-use std::{env, fs};
-use std::{path, process};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
 
 use pathsearch::find_executable_in_path;
+use serde::Serialize;
+
+use crate::config::{self, ShimDefaults, SunsetConfig};
+use crate::descriptor;
+use crate::error::{Result, SunsetError};
+use crate::project::ProjectOverride;
+use crate::shim::{self, ExpandSyntax, ShimConfig, ShimConfigEnvAction, ShimConfigEnvActionItem};
+
+/// Sunset context: where shims and shim templates live, and the defaults for new shims.
+///
+/// Every shim operation is a method of this context, so it can be embedded in other
+/// tools; presentation is left to the caller.
+#[derive(Debug)]
+pub struct Sunset {
+    pub shims_dir: PathBuf,
+    pub templates_dir: PathBuf,
+    pub options: ShimDefaults,
+    /// Interpreters by target extension from `sunset.toml`, before the built-in ones.
+    pub interpreters: BTreeMap<String, Vec<String>>,
+}
 
-use toml;
+/// Directories given explicitly, like in the command line, which take precedence over the
+/// environment and the configuration file.
+#[derive(Debug, Default, Clone)]
+pub struct DirOverrides {
+    pub shims_dir: Option<PathBuf>,
+    pub templates_dir: Option<PathBuf>,
+}
 
-pub fn shim(
-    target_path: &String,
-    args: &Vec<String>,
-    shim_name: &Option<String>,
-    win: &Option<bool>,
-    hidden: &Option<bool>,
-    no_wait: &Option<bool>,
-) {
-    let target_pathbuf = PathBuf::from(target_path);
+/// Parameters for a new shim. Flags left as `None` take the context defaults.
+#[derive(Debug, Default, Clone)]
+pub struct NewShim {
+    pub target: String,
+    pub name: Option<String>,
+    pub args: Vec<String>,
+    pub win: Option<bool>,
+    pub hidden: Option<bool>,
+    pub wait: Option<bool>,
+    pub env_expand_path: Option<bool>,
+    pub env_expand_args: Option<bool>,
+    /// Environment actions, applied in order.
+    pub env: Vec<ShimConfigEnvActionItem>,
+    /// Working directory for the target.
+    pub cwd: Option<String>,
+    pub group: Option<String>,
+    pub tags: Vec<String>,
+}
 
-    let target = match target_pathbuf.parent() {
-        Some(v) => {
-            if v == path::Path::new("") {
-                match find_executable_in_path(target_path) {
-                    Some(path) => path,
-                    None => {
-                        println!("Cannot find executable in PATH {}", target_path);
-                        process::exit(-1);
-                    }
-                }
-            } else {
-                println!("Parent {}", v.to_str().unwrap());
-                target_pathbuf
-                    .to_path_buf()
-                    .absolutize()
-                    .unwrap()
-                    .to_path_buf()
-            }
+#[derive(Debug, Clone, Serialize)]
+pub struct CreatedShim {
+    pub name: String,
+    pub target: PathBuf,
+    pub shimfile: PathBuf,
+    pub exe: PathBuf,
+    pub template: PathBuf,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RemovedShim {
+    pub name: String,
+    /// Descriptor removed, if it existed.
+    pub shimfile: Option<PathBuf>,
+    /// Executable removed, if it existed.
+    pub exe: Option<PathBuf>,
+    /// Aliases removed with the shim.
+    pub aliases: Vec<RemovedShim>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ShimInfo {
+    pub name: String,
+    pub shimfile: PathBuf,
+    /// Raw content of the descriptor.
+    pub content: String,
+    pub config: ShimConfig,
+}
+
+/// A shim after `disable` or `enable`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ToggledShim {
+    pub name: String,
+    pub exe: PathBuf,
+    pub disabled: bool,
+}
+
+/// A shim after `rename`.
+#[derive(Debug, Clone, Serialize)]
+pub struct RenamedShim {
+    pub name: String,
+    pub new_name: String,
+    pub shimfile: PathBuf,
+    pub exe: PathBuf,
+    /// Aliases updated to refer to the new name.
+    pub aliases: Vec<String>,
+    /// Shims updated to extend the new name.
+    pub extending: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UpgradedShim {
+    pub name: String,
+    pub exe: PathBuf,
+    pub template: PathBuf,
+}
+
+/// What a shim executes, with environment variables already expanded in path and args.
+#[derive(Debug, Clone, Serialize)]
+pub struct ResolvedShim {
+    pub name: String,
+    pub shimfile: PathBuf,
+    pub path: String,
+    pub args: Vec<String>,
+    pub env: Vec<ShimConfigEnvActionItem>,
+    pub config: ShimConfig,
+    /// Project file overriding the shim in the current directory, applied if trusted.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub project: Option<ProjectOverride>,
+}
+
+/// State of the executable of a shim compared to the current shim template.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExeState {
+    /// Same content as the template.
+    Current,
+    /// Different content than the template; `upgrade` relinks it.
+    Outdated,
+    /// There is no executable for the descriptor.
+    Missing,
+    /// The template to compare with doesn't exist.
+    Unknown,
+    /// The shim is disabled, so it has no executable on purpose.
+    Disabled,
+}
+
+/// Health of a shim: its descriptor, its target and its executable.
+#[derive(Debug, Clone, Serialize)]
+pub struct ShimStatus {
+    pub name: String,
+    pub shimfile: PathBuf,
+    /// Parsed descriptor, `None` if it couldn't be parsed.
+    pub config: Option<ShimConfig>,
+    /// Why the descriptor couldn't be parsed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Target path with environment variables expanded.
+    pub target: Option<String>,
+    pub target_exists: bool,
+    pub exe: PathBuf,
+    pub exe_state: ExeState,
+}
+
+impl ShimStatus {
+    /// A shim is broken when it cannot run: invalid descriptor, missing target or missing executable.
+    pub fn is_broken(&self) -> bool {
+        self.config.is_none() || !self.target_exists || self.exe_state == ExeState::Missing
+    }
+}
+
+impl Sunset {
+    pub fn new(shims_dir: PathBuf, templates_dir: PathBuf, options: ShimDefaults) -> Sunset {
+        Sunset {
+            shims_dir,
+            templates_dir,
+            options,
+            interpreters: BTreeMap::new(),
+        }
+    }
+
+    /// Builds the context from the environment and the configuration file.
+    ///
+    /// The shims dir is taken from `dirs`, then `SUNSET_SHIMS_PATH`, then `shims_path` in the
+    /// config file, defaulting to `%LOCALAPPDATA%\sunset\shims`. The templates dir is taken
+    /// from `dirs`, then `SUNSET_TEMPLATES_PATH`, then `templates_path`, defaulting to the
+    /// directory of the running executable.
+    pub fn from_config(config: SunsetConfig, dirs: DirOverrides) -> Result<Sunset> {
+        let shims_dir = match (dirs.shims_dir, env::var("SUNSET_SHIMS_PATH")) {
+            (Some(value), _) => value,
+            (None, Ok(value)) => PathBuf::from(value),
+            (None, Err(_)) => match config.shims_path {
+                Some(value) => value,
+                None => get_default_shims_dir()?,
+            },
+        };
+
+        let templates_dir = match (dirs.templates_dir, env::var("SUNSET_TEMPLATES_PATH")) {
+            (Some(value), _) => value,
+            (None, Ok(value)) => PathBuf::from(value),
+            (None, Err(_)) => match config.templates_path {
+                Some(value) => value,
+                None => get_exe_dir()?,
+            },
+        };
+
+        Ok(Sunset {
+            interpreters: config.interpreters,
+            ..Sunset::new(shims_dir, templates_dir, config.defaults)
+        })
+    }
+
+    /// Builds the context from the environment and the default configuration file.
+    pub fn load() -> Result<Sunset> {
+        Sunset::from_config(config::load()?, DirOverrides::default())
+    }
+
+    pub fn shimfile(&self, name: &str) -> PathBuf {
+        get_shimfile(&self.shims_dir, name)
+    }
+
+    pub fn shimmed_exe(&self, name: &str) -> PathBuf {
+        get_shimmed_exe(&self.shims_dir, name)
+    }
+
+    pub fn shim_exe(&self, win: bool) -> PathBuf {
+        get_shim_exe(&self.templates_dir, win)
+    }
+
+    /// Writes the descriptor for a new shim and links its executable.
+    pub fn create(&self, new_shim: &NewShim) -> Result<CreatedShim> {
+        let target = resolve_target(&new_shim.target)?;
+
+        let name = match &new_shim.name {
+            Some(name) => name.clone(),
+            None => get_default_name(&target)?,
+        };
+
+        let win = new_shim.win.unwrap_or(self.options.win);
+
+        let template = self.shim_exe(win);
+        let shimfile = self.shimfile(&name);
+        let exe = self.shimmed_exe(&name);
+
+        let shimfile_content = self.descriptor_table(&target.to_string_lossy(), new_shim);
+
+        let toml_content =
+            toml::to_string(&shimfile_content).map_err(|err| SunsetError::InvalidDescriptor {
+                path: shimfile.clone(),
+                message: err.to_string(),
+            })?;
+
+        fs::write(&shimfile, &toml_content).map_err(|err| SunsetError::io(&shimfile, err))?;
+
+        shim_create(&template, &exe)?;
+
+        Ok(CreatedShim {
+            name,
+            target,
+            shimfile,
+            exe,
+            template,
+        })
+    }
+
+    /// Descriptor of a new shim to `path`, leaving out the keys with default values.
+    pub(crate) fn descriptor_table(&self, path: &str, new_shim: &NewShim) -> toml::value::Table {
+        let win = new_shim.win.unwrap_or(self.options.win);
+        let hidden = new_shim.hidden.unwrap_or(self.options.hidden);
+        let wait = new_shim.wait.unwrap_or(self.options.wait);
+        let env_expand_path = new_shim
+            .env_expand_path
+            .unwrap_or(self.options.env_expand_path);
+        let env_expand_args = new_shim
+            .env_expand_args
+            .unwrap_or(self.options.env_expand_args);
+
+        let mut shimfile_content = toml::value::Table::new();
+
+        shimfile_content.insert(String::from("path"), toml::Value::from(path));
+        shimfile_content.insert(
+            String::from("args"),
+            toml::Value::from(new_shim.args.to_vec()),
+        );
+
+        if win {
+            shimfile_content.insert(String::from("win"), toml::Value::from(win));
+        }
+
+        if hidden {
+            shimfile_content.insert(String::from("hidden"), toml::Value::from(hidden));
         }
-        None => {
-            println!("Invalid path {}", target_path);
-            process::exit(-1);
+
+        if !wait {
+            shimfile_content.insert(String::from("wait"), toml::Value::from(wait));
         }
-    };
 
-    println!("Target {}", target.to_str().unwrap());
+        if env_expand_path {
+            shimfile_content.insert(String::from("env_expand_path"), toml::Value::from(true));
+        }
 
-    let shim_fullname = match shim_name {
-        None => String::from(
-            target
-                .with_extension("")
-                .file_stem()
-                .expect("Nope")
-                .to_str()
-                .expect("Nope"),
-        ),
-        Some(name) => String::from(name),
-    };
+        if env_expand_args {
+            shimfile_content.insert(String::from("env_expand_args"), toml::Value::from(true));
+        }
 
-    let sunset_dir = get_sunset_dir();
-    let shims_dir = get_shims_dir();
-    let shim_exe = get_shim_exe(&sunset_dir, win.as_ref().unwrap());
-    let shimfile_path = get_shimfile(&shims_dir, &shim_fullname);
-    let shimmed_exe_path = get_shimmed_exe(&shims_dir, &shim_fullname);
+        if self.options.env_expand_syntax != ExpandSyntax::Windows {
+            shimfile_content.insert(
+                String::from("env_expand_syntax"),
+                toml::Value::from(self.options.env_expand_syntax.name()),
+            );
+        }
 
-    println!(
-        "Shimming {:?}/ {:?} => {:?} using {:?}",
-        shimmed_exe_path, shimfile_path, target, shim_exe
-    );
+        if let Some(cwd) = &new_shim.cwd {
+            shimfile_content.insert(String::from("cwd"), toml::Value::from(cwd.as_str()));
+        }
+
+        if let Some(group) = &new_shim.group {
+            shimfile_content.insert(String::from("group"), toml::Value::from(group.as_str()));
+        }
+
+        if !new_shim.tags.is_empty() {
+            shimfile_content.insert(
+                String::from("tags"),
+                toml::Value::from(new_shim.tags.to_vec()),
+            );
+        }
 
-    let mut shimfile_content = toml::value::Table::new();
+        if !new_shim.env.is_empty() {
+            shimfile_content.insert(
+                String::from("env"),
+                toml::Value::from(new_shim.env.iter().map(env_item_table).collect::<Vec<_>>()),
+            );
+        }
 
-    let path_value = toml::Value::from(target.to_str().unwrap());
+        shimfile_content
+    }
 
-    let argsvec = toml::Value::from(args.to_vec());
+    /// Removes the descriptor and the executable of a shim. Missing files are ignored.
+    ///
+    /// A shim with aliases is only removed with `with_aliases`, which removes them too, so
+    /// they aren't left referring to a missing shim.
+    pub fn remove(&self, name: &str, with_aliases: bool) -> Result<RemovedShim> {
+        let aliases = self.aliases(name)?;
+
+        if !aliases.is_empty() && !with_aliases {
+            return Err(SunsetError::InvalidArgument(format!(
+                "Shim {:?} has aliases {}, remove them first or together with it",
+                name,
+                aliases.join(", ")
+            )));
+        }
 
-    shimfile_content.insert(String::from("path"), path_value);
-    shimfile_content.insert(String::from("args"), toml::Value::from(argsvec));
+        let aliases = aliases
+            .iter()
+            .map(|alias| self.remove(alias, false))
+            .collect::<Result<Vec<RemovedShim>>>()?;
+
+        let shimfile = self.shimfile(name);
+        let exe = self.shimmed_exe(name);
+
+        let shimfile = if shimfile.exists() {
+            fs::remove_file(&shimfile).map_err(|err| SunsetError::io(&shimfile, err))?;
+            Some(shimfile)
+        } else {
+            None
+        };
+
+        let exe = if exe.exists() {
+            fs::remove_file(&exe).map_err(|err| SunsetError::io(&exe, err))?;
+            Some(exe)
+        } else {
+            None
+        };
+
+        Ok(RemovedShim {
+            name: String::from(name),
+            shimfile,
+            exe,
+            aliases,
+        })
+    }
 
-    if win.unwrap() {
-        shimfile_content.insert(String::from("win"), toml::Value::from(win.unwrap()));
+    /// Names of the shims with a descriptor in the shims dir, sorted.
+    pub fn list(&self) -> Result<Vec<String>> {
+        let mut names = shim_list(&self.shims_dir)?;
+        names.sort();
+        Ok(names)
     }
 
-    if hidden.unwrap() {
-        shimfile_content.insert(String::from("hidden"), toml::Value::from(hidden.unwrap()));
+    pub fn info(&self, name: &str) -> Result<ShimInfo> {
+        let shimfile = self.existing_shimfile(name)?;
+
+        let content =
+            fs::read_to_string(&shimfile).map_err(|err| SunsetError::io(&shimfile, err))?;
+
+        let config = shim::parse_config_at(&shimfile, &content, None)?;
+
+        Ok(ShimInfo {
+            name: String::from(name),
+            shimfile,
+            content,
+            config,
+        })
     }
 
-    if no_wait.unwrap() {
-        shimfile_content.insert(String::from("no_wait"), toml::Value::from(no_wait.unwrap()));
+    /// Like `info`, with the content of the descriptor as the shim sees it: aliases followed
+    /// and the descriptors it `extends` merged in.
+    pub fn info_resolved(&self, name: &str) -> Result<ShimInfo> {
+        let mut info = self.info(name)?;
+        info.content = shim::resolved_descriptor(&info.shimfile)?;
+
+        Ok(info)
     }
 
-    let toml_content = match toml::to_string(&shimfile_content) {
-        Ok(text_content) => text_content,
-        Err(err) => {
-            println!("Cannot create TOML string: {:?}", err);
-            process::exit(-1);
-        }
-    };
+    /// Relinks the executable of a shim to the current template. Fails for disabled shims.
+    pub fn upgrade(&self, name: &str) -> Result<UpgradedShim> {
+        let shimfile = self.existing_shimfile(name)?;
+        let config = shim::read_config(&shimfile)?;
 
-    match fs::write(&shimfile_path, &toml_content) {
-        Ok(_) => {}
-        Err(err) => {
-            println!("Cannot write file {:?}: {}", shimfile_path, err);
-            process::exit(-1);
+        if config.disabled {
+            return Err(SunsetError::InvalidArgument(format!(
+                "Shim {:?} is disabled, enable it first",
+                name
+            )));
         }
+
+        let template = self.shim_exe(config.win);
+        let exe = self.shimmed_exe(name);
+
+        shim_create(&template, &exe)?;
+
+        Ok(UpgradedShim {
+            name: String::from(name),
+            exe,
+            template,
+        })
     }
 
-    shim_create(&shim_exe, &shimmed_exe_path);
+    /// Removes the executable of a shim, keeping its descriptor marked as disabled.
+    pub fn disable(&self, name: &str) -> Result<ToggledShim> {
+        self.edit_descriptor(name, |doc| descriptor::set_key(doc, "disabled", "true"))?;
 
-    println!("Done");
-}
+        Ok(ToggledShim {
+            name: String::from(name),
+            exe: self.shimmed_exe(name),
+            disabled: true,
+        })
+    }
+
+    /// Links the executable of a disabled shim again.
+    pub fn enable(&self, name: &str) -> Result<ToggledShim> {
+        self.edit_descriptor(name, |doc| descriptor::unset_key(doc, "disabled"))?;
 
-pub fn shim_path(name: &Option<String>) {
-    let name = match name {
-        None => {
-            println!("{}", get_shims_dir().to_str().unwrap());
-            process::exit(0);
+        Ok(ToggledShim {
+            name: String::from(name),
+            exe: self.shimmed_exe(name),
+            disabled: false,
+        })
+    }
+
+    /// Makes `variant` the active variant of a shim.
+    pub fn use_variant(&self, name: &str, variant: &str) -> Result<ShimConfig> {
+        self.edit_descriptor(name, |doc| descriptor::set_key(doc, "active", variant))
+    }
+
+    /// Renames a shim, moving its descriptor and executable, and updates its aliases.
+    ///
+    /// If the executable can't be moved, the descriptor is moved back.
+    pub fn rename(&self, name: &str, new_name: &str) -> Result<RenamedShim> {
+        let shimfile = self.existing_shimfile(name)?;
+        let new_shimfile = self.shimfile(new_name);
+        let exe = self.shimmed_exe(name);
+        let new_exe = self.shimmed_exe(new_name);
+
+        if new_shimfile.exists() || new_exe.exists() {
+            return Err(SunsetError::InvalidArgument(format!(
+                "Shim {:?} already exists",
+                new_name
+            )));
         }
-        Some(value) => value,
-    };
 
-    // Print path of shimfile if exists
-    let shimfile_path = get_shimfile(&get_shims_dir(), name);
-    println!("{}", shimfile_path.to_str().unwrap());
-}
+        let aliases = self.aliases(name)?;
+        let extending = self.extending(name)?;
 
-pub fn shim_info(name: &String) {
-    // Print path of shimfile if exists
-    let shimfile_path = get_shimfile(&get_shims_dir(), name);
+        fs::rename(&shimfile, &new_shimfile).map_err(|err| SunsetError::io(&shimfile, err))?;
 
-    let content = match std::fs::read_to_string(&shimfile_path) {
-        Ok(content) => content,
-        Err(err) => {
-            println!("Error reading shimfile: {:?}: {:?}", &shimfile_path, err);
-            process::exit(-1);
+        // Disabled shims have no executable to move.
+        let moved = if exe.exists() {
+            fs::rename(&exe, &new_exe)
+        } else {
+            Ok(())
+        };
+
+        if let Err(err) = moved {
+            let _ = fs::rename(&new_shimfile, &shimfile);
+            return Err(SunsetError::io(&exe, err));
         }
-    };
 
-    println!("{}", content);
-}
+        for alias in &aliases {
+            self.edit_descriptor(alias, |doc| {
+                descriptor::set_key(doc, "target_shim", new_name)
+            })?;
+        }
 
-pub fn get_sunset_dir() -> PathBuf {
-    return PathBuf::from(env::current_exe().unwrap().parent().unwrap());
-}
+        for other in &extending {
+            self.edit_descriptor(other, |doc| descriptor::rename_extends(doc, name, new_name))?;
+        }
 
-pub fn get_shims_dir() -> PathBuf {
-    let shims_path = env::var("SUNSET_SHIMS_PATH");
+        Ok(RenamedShim {
+            name: String::from(name),
+            new_name: String::from(new_name),
+            shimfile: new_shimfile,
+            exe: new_exe,
+            aliases,
+            extending,
+        })
+    }
 
-    let shims_path = match shims_path {
-        Err(err) => {
-            println!("SUNSET_SHIMS_PATH env var not specified: {}", err);
-            process::exit(-1);
+    /// Creates `alias`, a shim with the descriptor of `name`. Aliasing an alias aliases its
+    /// target instead.
+    pub fn alias(&self, alias: &str, name: &str) -> Result<CreatedShim> {
+        let shimfile = self.existing_shimfile(name)?;
+        let alias_shimfile = self.shimfile(alias);
+        let alias_exe = self.shimmed_exe(alias);
+
+        if alias_shimfile.exists() || alias_exe.exists() {
+            return Err(SunsetError::InvalidArgument(format!(
+                "Shim {:?} already exists",
+                alias
+            )));
         }
-        Ok(value) => value,
-    };
 
-    return PathBuf::from(shims_path);
-}
+        let content =
+            fs::read_to_string(&shimfile).map_err(|err| SunsetError::io(&shimfile, err))?;
+        let target_shim = shim::alias_of(&content).unwrap_or_else(|| String::from(name));
+        let config = shim::parse_config_at(&shimfile, &content, None)?;
+
+        let mut table = toml::value::Table::new();
+        table.insert(
+            String::from("target_shim"),
+            toml::Value::from(target_shim.as_str()),
+        );
+
+        let alias_content =
+            toml::to_string(&table).map_err(|err| SunsetError::InvalidDescriptor {
+                path: alias_shimfile.clone(),
+                message: err.to_string(),
+            })?;
+
+        let template = self.shim_exe(config.win);
+
+        write_atomically(&alias_shimfile, &alias_content)?;
+        shim_create(&template, &alias_exe)?;
+
+        Ok(CreatedShim {
+            name: String::from(alias),
+            target: PathBuf::from(config.path),
+            shimfile: alias_shimfile,
+            exe: alias_exe,
+            template,
+        })
+    }
 
-pub fn get_shim_exe(sunset_dir: &PathBuf, win: &bool) -> PathBuf {
-    let current_exe_base = if *win { "shimw.exe" } else { "shim.exe" };
+    /// Names of the aliases of a shim, whose descriptors have `target_shim = name`.
+    pub fn aliases(&self, name: &str) -> Result<Vec<String>> {
+        Ok(self
+            .list()?
+            .into_iter()
+            .filter(|other| {
+                fs::read_to_string(self.shimfile(other))
+                    .ok()
+                    .and_then(|content| shim::alias_of(&content))
+                    .is_some_and(|target| target == name)
+            })
+            .collect())
+    }
 
-    let current_exe: PathBuf = [&sunset_dir, &PathBuf::from(current_exe_base)]
-        .iter()
-        .collect();
-    return current_exe;
-}
+    /// Names of the shims whose descriptors extend shim `name`, rather than a template with
+    /// the same name.
+    pub fn extending(&self, name: &str) -> Result<Vec<String>> {
+        if !shim::extends_shim(&self.shims_dir, name) {
+            return Ok(Vec::new());
+        }
 
-pub fn get_shimmed_exe(shims_dir: &PathBuf, name: &String) -> PathBuf {
-    let exefile_basename = PathBuf::from(String::from(name) + ".exe");
-    let exefile_path: PathBuf = [&shims_dir, &exefile_basename].iter().collect();
-    return exefile_path;
-}
+        Ok(self
+            .list()?
+            .into_iter()
+            .filter(|other| {
+                fs::read_to_string(self.shimfile(other))
+                    .is_ok_and(|content| shim::extends_of(&content).iter().any(|it| it == name))
+            })
+            .collect())
+    }
 
-pub fn get_shimfile(shims_dir: &PathBuf, name: &String) -> PathBuf {
-    let shimfile_basename = PathBuf::from(String::from(name) + ".shim");
-    let shimfile_path: PathBuf = [&shims_dir, &shimfile_basename].iter().collect();
-    return shimfile_path;
-}
+    /// Whether the descriptor of a shim is marked as disabled.
+    pub fn is_disabled(&self, name: &str) -> bool {
+        shim::read_config(&self.shimfile(name)).is_ok_and(|config| config.disabled)
+    }
+
+    /// Reads the descriptor of a shim and expands its path and args like the shim would,
+    /// with the variant the shim would select and the overrides of a trusted project file.
+    pub fn resolve(&self, name: &str) -> Result<ResolvedShim> {
+        let shimfile = self.existing_shimfile(name)?;
+        let mut config = shim::read_config_for(&shimfile, name)?;
+        let project = shim::apply_project_override(&mut config, name)?;
+
+        let path = if config.env_expand_path {
+            shim::env_expand_with(&config.path, config.env_expand_syntax)
+        } else {
+            config.path.clone()
+        };
+
+        let args = if config.env_expand_args {
+            config
+                .args
+                .iter()
+                .map(|arg| shim::env_expand_with(arg, config.env_expand_syntax))
+                .collect()
+        } else {
+            config.args.clone()
+        };
+
+        Ok(ResolvedShim {
+            name: String::from(name),
+            shimfile,
+            path,
+            args,
+            env: config.env.clone(),
+            config,
+            project,
+        })
+    }
+
+    /// Replaces the descriptor of an existing shim with `content`.
+    ///
+    /// The content is validated first and the file is replaced atomically, so a failure leaves
+    /// the previous descriptor in place. If `win` changes, the executable is relinked to the
+    /// matching template; if `disabled` changes, the executable is removed or linked again.
+    pub fn update_descriptor(&self, name: &str, content: &str) -> Result<ShimConfig> {
+        let shimfile = self.existing_shimfile(name)?;
+        let previous = shim::read_config(&shimfile).ok();
+
+        let config = shim::parse_config_at(&shimfile, content, None)?;
+
+        write_atomically(&shimfile, content)?;
+
+        let exe = self.shimmed_exe(name);
+        let win_changed = previous
+            .as_ref()
+            .is_some_and(|previous| previous.win != config.win);
+
+        if config.disabled {
+            if exe.exists() {
+                fs::remove_file(&exe).map_err(|err| SunsetError::io(&exe, err))?;
+            }
+        } else if win_changed || previous.is_none_or(|previous| previous.disabled) {
+            shim_create(&self.shim_exe(config.win), &exe)?;
+        }
 
-pub fn shim_remove(name: &Option<String>) {
-    let name = match name {
-        None => {
-            println!("shim name not specified");
-            process::exit(-1);
+        // Aliases run the same target, so they need the same template.
+        if win_changed {
+            for alias in self.aliases(name)? {
+                if !self.is_disabled(&alias) {
+                    shim_create(&self.shim_exe(config.win), &self.shimmed_exe(&alias))?;
+                }
+            }
         }
-        Some(value) => value,
-    };
 
-    let shims_dir = get_shims_dir();
-    // Print path of shimfile if exists
-    let shimfile_path = get_shimfile(&shims_dir, name);
-    let shimmed_exe_path = get_shimmed_exe(&shims_dir, name);
+        Ok(config)
+    }
 
-    println!(
-        "Removing shim {:?} ({:?}, {:?})",
-        name, shimmed_exe_path, shimfile_path
-    );
+    /// Checks the descriptor, target and executable of a shim.
+    ///
+    /// Only fails if the descriptor doesn't exist; other problems are reported in the status.
+    pub fn status(&self, name: &str) -> Result<ShimStatus> {
+        let shimfile = self.existing_shimfile(name)?;
+        let exe = self.shimmed_exe(name);
+
+        let (config, error) = match shim::read_config(&shimfile) {
+            Ok(config) => (Some(config), None),
+            Err(err) => (None, Some(err.to_string())),
+        };
+
+        let target = config.as_ref().map(|config| {
+            if config.env_expand_path {
+                shim::env_expand_with(&config.path, config.env_expand_syntax)
+            } else {
+                config.path.clone()
+            }
+        });
+
+        let target_exists = target.as_deref().is_some_and(target_exists);
+
+        let win = config.as_ref().is_some_and(|config| config.win);
+
+        let exe_state = if config.as_ref().is_some_and(|config| config.disabled) {
+            ExeState::Disabled
+        } else {
+            get_exe_state(&exe, &self.shim_exe(win))
+        };
+
+        Ok(ShimStatus {
+            name: String::from(name),
+            shimfile,
+            config,
+            error,
+            target,
+            target_exists,
+            exe,
+            exe_state,
+        })
+    }
 
-    if shimfile_path.exists() {
-        fs::remove_file(shimfile_path).expect("Not deleted shimfile");
+    /// Names of the shims in `group`, sorted. Shims with invalid descriptors are left out.
+    pub fn group_members(&self, group: &str) -> Result<Vec<String>> {
+        Ok(self
+            .list()?
+            .into_iter()
+            .filter(|name| {
+                shim::read_config(&self.shimfile(name))
+                    .is_ok_and(|config| config.group.as_deref() == Some(group))
+            })
+            .collect())
     }
 
-    if shimmed_exe_path.exists() {
-        fs::remove_file(shimmed_exe_path).expect("Not deleted shimmed exe path");
+    /// Status of every shim, sorted by name.
+    pub fn list_status(&self) -> Result<Vec<ShimStatus>> {
+        self.list()?.iter().map(|name| self.status(name)).collect()
+    }
+
+    fn existing_shimfile(&self, name: &str) -> Result<PathBuf> {
+        let shimfile = self.shimfile(name);
+
+        if !shimfile.exists() {
+            return Err(SunsetError::ShimNotFound(String::from(name)));
+        }
+
+        Ok(shimfile)
     }
 }
 
-pub fn shim_create(shim_exe: &PathBuf, shimmed_exe_path: &PathBuf) {
-    if shimmed_exe_path.is_file() || shimmed_exe_path.is_symlink() {
-        println!("Removing {:?}", &shimmed_exe_path);
+/// Descriptor table of an env action, leaving out the keys with default values.
+fn env_item_table(item: &ShimConfigEnvActionItem) -> toml::value::Table {
+    let mut table = toml::value::Table::new();
+
+    table.insert(String::from("var"), toml::Value::from(item.var.as_str()));
+
+    if item.action != ShimConfigEnvAction::Set {
+        table.insert(
+            String::from("action"),
+            toml::Value::from(item.action.name()),
+        );
+    }
 
-        match fs::remove_file(&shimmed_exe_path) {
-            Ok(_) => {}
-            Err(err) => {
-                println!("Cannot remove {:?}: {}", &shimmed_exe_path, err);
-                process::exit(-1);
+    if item.action != ShimConfigEnvAction::Clear {
+        table.insert(
+            String::from("value"),
+            toml::Value::from(item.value.as_str()),
+        );
+    }
+
+    if !item.separator.is_empty() {
+        table.insert(
+            String::from("separator"),
+            toml::Value::from(item.separator.as_str()),
+        );
+    }
+
+    table
+}
+
+/// Resolves the target of a shim to an absolute path.
+///
+/// A bare name is searched in the PATH; anything else is made absolute.
+pub fn resolve_target(target_path: &str) -> Result<PathBuf> {
+    let target_pathbuf = PathBuf::from(target_path);
+
+    match target_pathbuf.parent() {
+        Some(v) => {
+            if v == Path::new("") {
+                find_executable_in_path(target_path)
+                    .ok_or(SunsetError::TargetNotFound(String::from(target_path)))
+            } else {
+                target_pathbuf
+                    .absolutize()
+                    .map(|path| path.to_path_buf())
+                    .map_err(|err| SunsetError::io(&target_pathbuf, err))
             }
         }
+        None => Err(SunsetError::TargetNotFound(String::from(target_path))),
     }
+}
 
-    println!("Creating: {:?}", &shimmed_exe_path);
-    match fs::hard_link(&shim_exe, &shimmed_exe_path) {
-        Ok(_) => {}
-        Err(err) => {
-            println!(
-                "Cannot hard-link {:?} to {:?}: {:?}",
-                &shim_exe, &shimmed_exe_path, err
-            );
-            process::exit(-1);
-        }
+/// Whether a shim target exists: as a file if it has a directory, otherwise in the PATH.
+pub fn target_exists(target: &str) -> bool {
+    let target_path = Path::new(target);
+
+    match target_path.parent() {
+        Some(parent) if parent != Path::new("") => target_path.is_file(),
+        _ => find_executable_in_path(target).is_some(),
     }
 }
 
-pub fn shim_list(shim_dir: &PathBuf) -> Vec<String> {
-    let files = fs::read_dir(shim_dir).unwrap();
+/// Compares the executable of a shim with the template it should be linked to.
+pub fn get_exe_state(exe: &Path, template: &Path) -> ExeState {
+    if !exe.is_file() {
+        return ExeState::Missing;
+    }
+
+    match same_content(exe, template) {
+        Ok(true) => ExeState::Current,
+        Ok(false) => ExeState::Outdated,
+        Err(_) => ExeState::Unknown,
+    }
+}
+
+fn same_content(a: &Path, b: &Path) -> std::io::Result<bool> {
+    if fs::metadata(a)?.len() != fs::metadata(b)?.len() {
+        return Ok(false);
+    }
+
+    Ok(fs::read(a)? == fs::read(b)?)
+}
+
+/// Name of a shim for `target` when none is given: the file name without extension.
+pub fn get_default_name(target: &Path) -> Result<String> {
+    target
+        .with_extension("")
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .map(String::from)
+        .ok_or(SunsetError::TargetNotFound(
+            target.to_string_lossy().into_owned(),
+        ))
+}
+
+fn get_exe_dir() -> Result<PathBuf> {
+    let current_exe = env::current_exe().map_err(|err| SunsetError::io("", err))?;
+
+    current_exe
+        .parent()
+        .map(PathBuf::from)
+        .ok_or(SunsetError::Setting(String::from(
+            "Cannot get the directory of the current executable",
+        )))
+}
+
+pub fn get_default_shims_dir() -> Result<PathBuf> {
+    match env::var("LOCALAPPDATA") {
+        Ok(value) => Ok(Path::new(&value).join("sunset").join("shims")),
+        Err(err) => Err(SunsetError::Setting(format!(
+            "Failed to get value of LOCALAPPDATA: {}",
+            err
+        ))),
+    }
+}
+
+pub fn get_shim_exe(templates_dir: &Path, win: bool) -> PathBuf {
+    let current_exe_base = if win { "shimw.exe" } else { "shim.exe" };
+
+    templates_dir.join(current_exe_base)
+}
+
+pub fn get_shimmed_exe(shims_dir: &Path, name: &str) -> PathBuf {
+    shims_dir.join(String::from(name) + ".exe")
+}
+
+pub fn get_shimfile(shims_dir: &Path, name: &str) -> PathBuf {
+    shims_dir.join(String::from(name) + ".shim")
+}
+
+/// Writes `content` to a temporary file next to `path` and renames it over `path`.
+pub fn write_atomically(path: &Path, content: &str) -> Result<()> {
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".tmp");
+    let tmp_path = path.with_file_name(tmp_name);
+
+    fs::write(&tmp_path, content).map_err(|err| SunsetError::io(&tmp_path, err))?;
+
+    fs::rename(&tmp_path, path).map_err(|err| {
+        let _ = fs::remove_file(&tmp_path);
+        SunsetError::io(path, err)
+    })
+}
+
+/// Hard-links the shim template `shim_exe` to `shimmed_exe_path`, replacing any existing file.
+pub fn shim_create(shim_exe: &Path, shimmed_exe_path: &Path) -> Result<()> {
+    if shimmed_exe_path.is_file() || shimmed_exe_path.is_symlink() {
+        fs::remove_file(shimmed_exe_path).map_err(|err| SunsetError::io(shimmed_exe_path, err))?;
+    }
+
+    fs::hard_link(shim_exe, shimmed_exe_path).map_err(|err| SunsetError::io(shimmed_exe_path, err))
+}
 
-    return files
-        .map(|it| it.unwrap().path())
-        .filter(|it| str::ends_with(it.file_name().unwrap().to_str().unwrap(), ".shim"))
-        .map(|it| String::from(it.file_stem().unwrap().to_str().unwrap()))
-        .collect::<Vec<String>>();
+/// Names of the `.shim` descriptors in `shim_dir`.
+pub fn shim_list(shim_dir: &Path) -> Result<Vec<String>> {
+    let files = fs::read_dir(shim_dir).map_err(|err| SunsetError::io(shim_dir, err))?;
+
+    Ok(files
+        .flatten()
+        .map(|it| it.path())
+        .filter(|it| it.extension().is_some_and(|ext| ext == "shim"))
+        .filter_map(|it| {
+            it.file_stem()
+                .and_then(|stem| stem.to_str())
+                .map(String::from)
+        })
+        .collect::<Vec<String>>())
 }
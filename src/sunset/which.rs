@@ -0,0 +1,95 @@
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+use crate::error::{Result, SunsetError};
+use crate::project::ProjectOverride;
+use crate::search;
+use crate::shim::ShimConfigEnvActionItem;
+use crate::shimmer::Sunset;
+
+/// A shim traversed while resolving a command.
+#[derive(Debug, Clone, Serialize)]
+pub struct WhichHop {
+    pub name: String,
+    pub exe: PathBuf,
+    pub shimfile: PathBuf,
+    /// Project file overriding the shim in the current directory, applied if trusted.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub project: Option<ProjectOverride>,
+}
+
+/// What actually executes when running a command.
+#[derive(Debug, Clone, Serialize)]
+pub struct Which {
+    pub command: String,
+    /// Executable the OS finds for the command.
+    pub found: PathBuf,
+    /// Shims traversed from `found` to `target`; empty if `found` is not a shim.
+    pub chain: Vec<WhichHop>,
+    /// Executable finally run.
+    pub target: PathBuf,
+    /// Arguments prepended by the shims to the ones given to the command.
+    pub args: Vec<String>,
+    /// Environment changes made by the shims, in the order they are applied.
+    pub env: Vec<ShimConfigEnvActionItem>,
+}
+
+impl Sunset {
+    /// Resolves `command` like the OS would, following shims to the executable finally run.
+    pub fn which(&self, command: &str) -> Result<Which> {
+        let found =
+            search::find(command).ok_or(SunsetError::TargetNotFound(String::from(command)))?;
+
+        let mut chain: Vec<WhichHop> = Vec::new();
+        let mut args: Vec<String> = Vec::new();
+        let mut env: Vec<ShimConfigEnvActionItem> = Vec::new();
+        let mut current = found.clone();
+
+        while let Some(name) = self.shim_name_for(&current) {
+            if chain.iter().any(|hop| hop.name == name) {
+                let mut names: Vec<String> = chain.into_iter().map(|hop| hop.name).collect();
+                names.push(name);
+                return Err(SunsetError::Cycle(names));
+            }
+
+            let resolved = self.resolve(&name)?;
+
+            // An inner shim gets the outer args after its own, and applies its env on top.
+            args.splice(0..0, resolved.args);
+            env.extend(resolved.env);
+
+            chain.push(WhichHop {
+                name,
+                exe: current,
+                shimfile: resolved.shimfile,
+                project: resolved.project,
+            });
+
+            current = search::find(&resolved.path)
+                .ok_or(SunsetError::TargetNotFound(resolved.path.clone()))?;
+        }
+
+        Ok(Which {
+            command: String::from(command),
+            found,
+            chain,
+            target: current,
+            args,
+            env,
+        })
+    }
+
+    /// Name of the shim `exe` is, if it is an executable in the shims dir with a descriptor.
+    pub fn shim_name_for(&self, exe: &Path) -> Option<String> {
+        let in_shims_dir = exe
+            .parent()
+            .is_some_and(|dir| search::is_same_dir(dir, &self.shims_dir));
+
+        if !in_shims_dir || !exe.with_extension("shim").is_file() {
+            return None;
+        }
+
+        exe.file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+    }
+}